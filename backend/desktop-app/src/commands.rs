@@ -1,18 +1,38 @@
 use super::state::AppState;
-use log::info;
+use super::sync::SYNC_STATUS_EVENT;
+use log::{error, info};
 use serde::Serialize;
-use services::note_service::{CreateNote, Note, NoteServiceError, UpdateNote};
+use services::diff::NoteDiff;
+use services::links::{Backlink, BrokenLinks};
+use services::note_service::{
+    Attachment, CreateNote, DeleteReport, Note, NoteCommit, NoteConflict, NoteServiceError, NoteTree, SavedNote,
+    UpdateNote,
+};
+use services::search::SearchHit;
+use services::store::SyncStatus;
+use services::tags::{TagCount, TaggedNote};
+use tauri::Emitter;
 
 #[derive(Debug, Serialize)]
 pub enum TauriError {
     NoteAlreadyExists,
+    NotFound,
+    Conflict(NoteConflict),
     GitHub(String),
+    Io(String),
+    Git(String),
+    Unsupported(String),
+    InvalidQuery(String),
+    InvalidAttachment(String),
+    InvalidPath(String),
+    ContentUnavailable(String),
+    RateLimited { reset_at: Option<String> },
     Anyhow(String),
 }
 
 impl From<anyhow::Error> for TauriError {
     fn from(err: anyhow::Error) -> Self {
-        TauriError::Anyhow(err.to_string())
+        NoteServiceError::from(err).into()
     }
 }
 
@@ -20,7 +40,17 @@ impl From<NoteServiceError> for TauriError {
     fn from(err: NoteServiceError) -> Self {
         match err {
             NoteServiceError::NoteAlreadyExists => TauriError::NoteAlreadyExists,
+            NoteServiceError::NotFound => TauriError::NotFound,
+            NoteServiceError::Conflict(conflict) => TauriError::Conflict(conflict),
             NoteServiceError::GitHub(s) => TauriError::GitHub(s),
+            NoteServiceError::Io(s) => TauriError::Io(s),
+            NoteServiceError::Git(s) => TauriError::Git(s),
+            NoteServiceError::Unsupported(s) => TauriError::Unsupported(s),
+            NoteServiceError::InvalidQuery(s) => TauriError::InvalidQuery(s),
+            NoteServiceError::InvalidAttachment(s) => TauriError::InvalidAttachment(s),
+            NoteServiceError::InvalidPath(s) => TauriError::InvalidPath(s),
+            NoteServiceError::ContentUnavailable(s) => TauriError::ContentUnavailable(s),
+            NoteServiceError::RateLimited { reset_at } => TauriError::RateLimited { reset_at },
             NoteServiceError::Anyhow(s) => TauriError::Anyhow(s),
         }
     }
@@ -44,16 +74,23 @@ pub fn set_credentials(
     *state.github_token.lock().unwrap() = Some(github_token);
     *state.notes_repo.lock().unwrap() = Some(notes_repo);
     *state.app_identifier.lock().unwrap() = Some(app_identifier);
+    state.reset_store();
     *initialized = true;
     Ok(())
 }
 
 #[tauri::command]
-pub async fn list_notes(state: tauri::State<'_, AppState>) -> Result<Vec<Note>, TauriError> {
+pub async fn list_notes(
+    state: tauri::State<'_, AppState>,
+    include_metadata: Option<bool>,
+) -> Result<NoteTree, TauriError> {
     info!("Listing all notes");
     let service = state.get_service()?;
     info!("Fetching all notes from service");
-    service.get_all_notes().await.map_err(Into::into)
+    match include_metadata {
+        Some(true) => service.get_all_notes_with_metadata().await.map_err(Into::into),
+        _ => service.get_all_notes().await.map_err(Into::into),
+    }
 }
 
 #[tauri::command]
@@ -63,6 +100,69 @@ pub async fn get_note(state: tauri::State<'_, AppState>, path: String) -> Result
     service.get_note(&path).await.map_err(Into::into)
 }
 
+#[tauri::command]
+pub async fn get_note_history(state: tauri::State<'_, AppState>, path: String) -> Result<Vec<NoteCommit>, TauriError> {
+    info!("Getting history of note at path: {}", path);
+    let service = state.get_service()?;
+    service.get_note_history(&path).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn get_backlinks(state: tauri::State<'_, AppState>, path: String) -> Result<Vec<Backlink>, TauriError> {
+    info!("Getting backlinks of note at path: {}", path);
+    let service = state.get_service()?;
+    service.get_backlinks(&path).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn get_note_at_revision(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    rev: String,
+) -> Result<Option<Note>, TauriError> {
+    info!("Getting note at path: {} in revision {}", path, rev);
+    let service = state.get_service()?;
+    service.get_note_at(&path, &rev).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn diff_note(
+    state: tauri::State<'_, AppState>,
+    path: String,
+    from: String,
+    to: Option<String>,
+) -> Result<NoteDiff, TauriError> {
+    info!("Diffing note at path: {} from {} to {:?}", path, from, to);
+    let service = state.get_service()?;
+    service.diff_note(&path, &from, to.as_deref()).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn restore_note(state: tauri::State<'_, AppState>, path: String, rev: String) -> Result<(), TauriError> {
+    info!("Restoring note at path: {} to revision {}", path, rev);
+    let service = state.get_service()?;
+    service.restore_note(&path, &rev).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn move_note(state: tauri::State<'_, AppState>, from: String, to: String) -> Result<(), TauriError> {
+    info!("Moving note from {} to {}", from, to);
+    let service = state.get_service()?;
+    service.move_note(&from, &to).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn copy_note(
+    state: tauri::State<'_, AppState>,
+    from: String,
+    to: String,
+    overwrite: bool,
+) -> Result<(), TauriError> {
+    info!("Copying note from {} to {}", from, to);
+    let service = state.get_service()?;
+    service.copy_note(&from, &to, overwrite).await.map_err(Into::into)
+}
+
 #[tauri::command]
 pub async fn create_note(state: tauri::State<'_, AppState>, payload: CreateNote) -> Result<(), TauriError> {
     info!("Creating note at path: {}", payload.path);
@@ -75,7 +175,7 @@ pub async fn update_note(
     state: tauri::State<'_, AppState>,
     path: String,
     payload: UpdateNote,
-) -> Result<(), TauriError> {
+) -> Result<SavedNote, TauriError> {
     info!("Updating note at path: {}", path);
     let service = state.get_service()?;
     service.update_note(&path, &payload).await.map_err(Into::into)
@@ -88,6 +188,119 @@ pub async fn delete_note(state: tauri::State<'_, AppState>, path: String) -> Res
     service.delete_note(&path).await.map_err(Into::into)
 }
 
+#[tauri::command]
+pub async fn delete_tree(state: tauri::State<'_, AppState>, path: String) -> Result<DeleteReport, TauriError> {
+    info!("Deleting category at path: {}", path);
+    let service = state.get_service()?;
+    service.delete_tree(&path).await.map_err(Into::into)
+}
+
+/// Stores an attachment in the `assets/` folder of `category`, from either `bytes` or the local
+/// file at `file_path`. `name` defaults to the name of that file.
+#[tauri::command]
+pub async fn add_attachment(
+    state: tauri::State<'_, AppState>,
+    category: String,
+    name: Option<String>,
+    bytes: Option<Vec<u8>>,
+    file_path: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<Attachment, TauriError> {
+    info!("Adding attachment to category: {}", category);
+    let (content, file_name) = match (bytes, file_path) {
+        (Some(bytes), _) => (bytes, None),
+        (None, Some(file_path)) => {
+            let path = std::path::Path::new(&file_path);
+            let content = tokio::fs::read(path).await.map_err(|e| TauriError::Io(e.to_string()))?;
+            (
+                content,
+                path.file_name().map(|name| name.to_string_lossy().into_owned()),
+            )
+        }
+        (None, None) => {
+            return Err(TauriError::InvalidAttachment(
+                "either bytes or file_path is required".to_string(),
+            ));
+        }
+    };
+    let Some(name) = name.or(file_name) else {
+        return Err(TauriError::InvalidAttachment("name is required".to_string()));
+    };
+    let service = state.get_service()?;
+    service
+        .add_attachment(&category, &name, content, overwrite.unwrap_or(false))
+        .await
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn get_attachment(state: tauri::State<'_, AppState>, path: String) -> Result<Option<Vec<u8>>, TauriError> {
+    info!("Getting attachment at path: {}", path);
+    let service = state.get_service()?;
+    service.get_attachment(&path).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn search_notes(state: tauri::State<'_, AppState>, query: String) -> Result<Vec<SearchHit>, TauriError> {
+    info!("Searching notes for: {}", query);
+    let service = state.get_service()?;
+    service.search(&query).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn list_tags(state: tauri::State<'_, AppState>) -> Result<Vec<TagCount>, TauriError> {
+    info!("Listing tags");
+    let service = state.get_service()?;
+    service.get_tags().await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn get_tagged_notes(state: tauri::State<'_, AppState>, tag: String) -> Result<Vec<TaggedNote>, TauriError> {
+    info!("Getting notes tagged: {}", tag);
+    let service = state.get_service()?;
+    service.get_notes_by_tag(&tag).await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub async fn check_links(state: tauri::State<'_, AppState>) -> Result<Vec<BrokenLinks>, TauriError> {
+    info!("Checking links");
+    let service = state.get_service()?;
+    service.check_links().await.map_err(Into::into)
+}
+
+#[tauri::command]
+pub fn get_sync_status(state: tauri::State<'_, AppState>) -> Result<Option<SyncStatus>, TauriError> {
+    info!("Getting sync status");
+    Ok(state.cache.lock().unwrap().as_ref().map(|cache| cache.status()))
+}
+
+#[tauri::command]
+pub async fn sync_notes(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<SyncStatus>, TauriError> {
+    info!("Syncing notes");
+    let Some(cache) = state.get_cache()? else {
+        return Ok(None);
+    };
+    let status = cache.sync().await;
+    state.search_index.lock().unwrap().invalidate();
+    if let Err(e) = app.emit(SYNC_STATUS_EVENT, &status) {
+        error!("Failed to emit sync status: {}", e);
+    }
+    Ok(Some(status))
+}
+
+#[tauri::command]
+pub fn dismiss_conflict(state: tauri::State<'_, AppState>, path: String) -> Result<Option<SyncStatus>, TauriError> {
+    info!("Dismissing sync conflict: {}", path);
+    let Some(cache) = state.get_cache()? else {
+        return Ok(None);
+    };
+    cache.dismiss_conflict(&path)?;
+    Ok(Some(cache.status()))
+}
+
 #[tauri::command]
 pub fn log_message(message: String) {
     info!("[Frontend] {}", message);
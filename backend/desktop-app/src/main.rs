@@ -1,16 +1,22 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use log::{error, info};
+use services::search::SearchIndex;
+use tauri::Manager;
 use tauri_plugin_log::TimezoneStrategy;
 
 mod commands;
 mod state;
+mod sync;
 mod tray;
 
 use commands::{
-    create_note, delete_note, get_note, is_initialized, list_notes, log_message, set_credentials, update_note,
+    add_attachment, check_links, copy_note, create_note, delete_note, delete_tree, diff_note, dismiss_conflict,
+    get_attachment, get_backlinks, get_note, get_note_at_revision, get_note_history, get_sync_status, get_tagged_notes,
+    is_initialized, list_notes, list_tags, log_message, move_note, restore_note, search_notes, set_credentials,
+    sync_notes, update_note,
 };
 use state::AppState;
 
@@ -20,6 +26,10 @@ fn main() {
         notes_repo: Mutex::new(None),
         app_identifier: Mutex::new(None),
         initialized: Mutex::new(false),
+        data_dir: Mutex::new(None),
+        store: Mutex::new(None),
+        cache: Mutex::new(None),
+        search_index: Mutex::new(Arc::new(SearchIndex::new())),
     };
 
     tauri::Builder::default()
@@ -52,6 +62,12 @@ fn main() {
                 Ok(current_dir) => info!("Current working directory: {:?}", current_dir),
                 Err(e) => error!("Failed to get current working directory: {}", e),
             }
+            match _app.path().app_data_dir() {
+                Ok(data_dir) => *_app.state::<AppState>().data_dir.lock().unwrap() = Some(data_dir),
+                Err(e) => error!("Failed to resolve the app data directory: {}", e),
+            }
+            tauri::async_runtime::spawn(sync::run(_app.handle().clone()));
+
             info!("Tauri server v{} is starting up", env!("CARGO_PKG_VERSION"));
             Ok(())
         })
@@ -61,9 +77,26 @@ fn main() {
             is_initialized,
             list_notes,
             get_note,
+            get_note_history,
+            get_backlinks,
+            get_note_at_revision,
+            diff_note,
+            restore_note,
+            move_note,
+            copy_note,
             create_note,
             update_note,
             delete_note,
+            delete_tree,
+            add_attachment,
+            get_attachment,
+            search_notes,
+            list_tags,
+            get_tagged_notes,
+            check_links,
+            get_sync_status,
+            sync_notes,
+            dismiss_conflict,
             log_message
         ])
         .run(tauri::generate_context!())
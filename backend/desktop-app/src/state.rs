@@ -1,6 +1,11 @@
 use log::info;
 use services::note_service::{NoteService, NoteServiceError};
-use std::sync::Mutex;
+use services::search::SearchIndex;
+use services::store::{self, CachedStore, NoteStore, NotesSource};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+pub type NoteCache = CachedStore<Box<dyn NoteStore>>;
 
 // --- State Management ---
 pub struct AppState {
@@ -8,19 +13,44 @@ pub struct AppState {
     pub notes_repo: Mutex<Option<String>>,
     pub app_identifier: Mutex<Option<String>>,
     pub initialized: Mutex<bool>,
+    /// The app data directory, where GitHub repositories are cached for offline use.
+    pub data_dir: Mutex<Option<PathBuf>>,
+    pub store: Mutex<Option<Arc<dyn NoteStore>>>,
+    pub cache: Mutex<Option<Arc<NoteCache>>>,
+    /// Search index over the notes of the open store, saved in the app data directory.
+    pub search_index: Mutex<Arc<SearchIndex>>,
 }
 
 impl AppState {
     /// Creates a NoteService instance from the current state.
-    pub fn get_service(&self) -> Result<NoteService, NoteServiceError> {
+    pub fn get_service(&self) -> Result<NoteService<Arc<dyn NoteStore>>, NoteServiceError> {
         info!("Creating NoteService from state");
+        let app_identifier = self
+            .app_identifier
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| "NoteApp".to_string());
+
+        let store = self.get_store()?;
+        let search_index = self.search_index.lock().unwrap().clone();
+        Ok(NoteService::with_store(store, app_identifier).with_search_index(search_index))
+    }
+
+    /// Returns the store for the current credentials, opening it on first use.
+    fn get_store(&self) -> Result<Arc<dyn NoteStore>, NoteServiceError> {
+        let mut store = self.store.lock().unwrap();
+        if let Some(store) = store.as_ref() {
+            return Ok(store.clone());
+        }
+
         // 1. Lock mutexes and extract credentials.
         let github_token = self
             .github_token
             .lock()
             .unwrap()
             .clone()
-            .ok_or_else(|| NoteServiceError::Anyhow("GitHub token not set".to_string()))?;
+            .filter(|token| !token.is_empty());
 
         let notes_repo = self
             .notes_repo
@@ -29,14 +59,41 @@ impl AppState {
             .clone()
             .ok_or_else(|| NoteServiceError::Anyhow("Notes repo not set".to_string()))?;
 
-        let app_identifier = self
-            .app_identifier
-            .lock()
-            .unwrap()
-            .clone()
-            .unwrap_or_else(|| "NoteApp".to_string());
+        // 2. Open the backend, caching GitHub repositories so they stay usable offline.
+        let remote = store::open(github_token, &notes_repo)?;
+        let data_dir = self.data_dir.lock().unwrap().clone();
+        let opened: Arc<dyn NoteStore> = match (NotesSource::parse(&notes_repo), data_dir.clone()) {
+            (NotesSource::GitHub(repo_name), Some(data_dir)) => {
+                let cache_dir = data_dir.join("cache").join(cache_key(&repo_name));
+                info!("Caching {} in {:?}", repo_name, cache_dir);
+                let cache = Arc::new(CachedStore::open(remote, cache_dir)?);
+                *self.cache.lock().unwrap() = Some(cache.clone());
+                cache
+            }
+            _ => Arc::from(remote),
+        };
+        if let Some(data_dir) = data_dir {
+            let index_file = data_dir.join("search").join(format!("{}.json", cache_key(&notes_repo)));
+            *self.search_index.lock().unwrap() = Arc::new(SearchIndex::persistent(index_file));
+        }
+        *store = Some(opened.clone());
+        Ok(opened)
+    }
+
+    /// Returns the offline cache, if the current notes repository is cached.
+    pub fn get_cache(&self) -> Result<Option<Arc<NoteCache>>, NoteServiceError> {
+        self.get_store()?;
+        Ok(self.cache.lock().unwrap().clone())
+    }
 
-        // 2. Create and return the service.
-        NoteService::new(github_token, notes_repo, app_identifier)
+    /// Drops the open store so the next call to `get_service` uses new credentials.
+    pub fn reset_store(&self) {
+        *self.store.lock().unwrap() = None;
+        *self.cache.lock().unwrap() = None;
+        *self.search_index.lock().unwrap() = Arc::new(SearchIndex::new());
     }
 }
+
+fn cache_key(repo_name: &str) -> String {
+    repo_name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_")
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use log::{error, info};
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::state::AppState;
+
+pub const SYNC_STATUS_EVENT: &str = "sync-status";
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Syncs the note cache with GitHub every `SYNC_INTERVAL`, or as soon as a change is queued,
+/// and reports the outcome to the frontend through the `sync-status` event.
+pub async fn run(app: AppHandle) {
+    loop {
+        let cache = app.state::<AppState>().cache.lock().unwrap().clone();
+        let Some(cache) = cache else {
+            tokio::time::sleep(SYNC_INTERVAL).await;
+            continue;
+        };
+
+        info!("Syncing note cache");
+        let status = cache.sync().await;
+        // The sync may have pulled changes the search index has not seen.
+        app.state::<AppState>().search_index.lock().unwrap().invalidate();
+        if let Err(e) = app.emit(SYNC_STATUS_EVENT, &status) {
+            error!("Failed to emit sync status: {}", e);
+        }
+        let _ = tokio::time::timeout(SYNC_INTERVAL, cache.changed()).await;
+    }
+}
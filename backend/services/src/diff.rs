@@ -0,0 +1,131 @@
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    /// 1-based line number in the old content, for context and removed lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<usize>,
+    /// 1-based line number in the new content, for context and added lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<usize>,
+    pub content: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DiffHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// A line diff between two versions of a note, both as a unified diff and as structured hunks.
+#[derive(Serialize, Debug, Clone)]
+pub struct NoteDiff {
+    pub unified: String,
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// Diffs `old` against `new` line by line. `old_label` and `new_label` name the two sides in the
+/// unified diff header.
+pub fn diff(old: &str, new: &str, old_label: &str, new_label: &str) -> NoteDiff {
+    let text_diff = TextDiff::from_lines(old, new);
+    let unified = text_diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(old_label, new_label)
+        .to_string();
+
+    let hunks = text_diff
+        .grouped_ops(CONTEXT_LINES)
+        .iter()
+        .filter_map(|group| {
+            let (first, last) = (group.first()?, group.last()?);
+            let lines = group
+                .iter()
+                .flat_map(|op| text_diff.iter_changes(op))
+                .map(|change| DiffLine {
+                    kind: match change.tag() {
+                        ChangeTag::Equal => DiffLineKind::Context,
+                        ChangeTag::Insert => DiffLineKind::Added,
+                        ChangeTag::Delete => DiffLineKind::Removed,
+                    },
+                    old_line: change.old_index().map(|index| index + 1),
+                    new_line: change.new_index().map(|index| index + 1),
+                    content: change.value().trim_end_matches(['\r', '\n']).to_string(),
+                })
+                .collect();
+            Some(DiffHunk {
+                old_start: first.old_range().start + 1,
+                old_lines: last.old_range().end - first.old_range().start,
+                new_start: first.new_range().start + 1,
+                new_lines: last.new_range().end - first.new_range().start,
+                lines,
+            })
+        })
+        .collect();
+
+    NoteDiff { unified, hunks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_changed_lines() {
+        let diff = diff("a\nb\nc\n", "a\nB\nc\nd\n", "old", "new");
+        assert!(diff.unified.starts_with("--- old\n+++ new\n"));
+        assert_eq!(diff.hunks.len(), 1);
+
+        let hunk = &diff.hunks[0];
+        assert_eq!(
+            (hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines),
+            (1, 3, 1, 4)
+        );
+        let lines: Vec<_> = hunk
+            .lines
+            .iter()
+            .map(|line| (line.kind, line.old_line, line.new_line, line.content.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (DiffLineKind::Context, Some(1), Some(1), "a"),
+                (DiffLineKind::Removed, Some(2), None, "b"),
+                (DiffLineKind::Added, None, Some(2), "B"),
+                (DiffLineKind::Context, Some(3), Some(3), "c"),
+                (DiffLineKind::Added, None, Some(4), "d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_distant_changes_into_hunks() {
+        let old: String = (1..=20).map(|line| format!("{}\n", line)).collect();
+        let new = old.replacen("2\n", "two\n", 1).replacen("19\n", "nineteen\n", 1);
+        let diff = diff(&old, &new, "old", "new");
+        assert_eq!(diff.hunks.len(), 2);
+        assert_eq!(diff.hunks[1].old_start, 16);
+    }
+
+    #[test]
+    fn has_no_hunks_without_changes() {
+        let diff = diff("same\n", "same\n", "old", "new");
+        assert!(diff.hunks.is_empty());
+        assert!(diff.unified.is_empty());
+    }
+}
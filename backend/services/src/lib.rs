@@ -1 +1,9 @@
+pub mod diff;
+pub mod links;
+pub mod merge;
+pub mod metadata;
 pub mod note_service;
+pub mod query;
+pub mod search;
+pub mod store;
+pub mod tags;
@@ -0,0 +1,358 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// `[[other-note]]`, resolved by note name.
+    Wiki,
+    /// `[text](../other-note.md)`, resolved relative to the linking note.
+    Markdown,
+    /// `![alt](../image.png)`, resolved like a Markdown link.
+    Image,
+}
+
+/// A line of a note that links to another note.
+#[derive(Serialize, Debug, Clone)]
+pub struct LinkContext {
+    /// 1-based line number of the link.
+    pub line: usize,
+    /// The line the link is written in.
+    pub context: String,
+}
+
+/// A note that links to another note.
+#[derive(Serialize, Debug, Clone)]
+pub struct Backlink {
+    pub id: String,
+    pub name: String,
+    pub links: Vec<LinkContext>,
+}
+
+/// A link that points to no note or attachment.
+#[derive(Serialize, Debug, Clone)]
+pub struct BrokenLink {
+    pub kind: LinkKind,
+    /// The link target as written.
+    pub target: String,
+    /// 1-based line number of the link.
+    pub line: usize,
+    /// The line the link is written in.
+    pub context: String,
+}
+
+/// The broken links of a note.
+#[derive(Serialize, Debug, Clone)]
+pub struct BrokenLinks {
+    pub id: String,
+    pub name: String,
+    pub links: Vec<BrokenLink>,
+}
+
+/// A link written in a note, before it is resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// The linked note as written, without any alias, `#heading` or `?query`.
+    pub target: String,
+    /// 1-based line number of the link in the note.
+    pub line: usize,
+    /// Byte offset of the link in its line.
+    pub offset: usize,
+}
+
+/// The links between notes written in `content`, outside of code. Links to other sites and to
+/// headings in the same note are left out.
+pub fn extract(content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut in_code = false;
+    for (index, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        let line_text = without_code_spans(line);
+        for (offset, kind, target) in wiki_links(&line_text).chain(markdown_links(&line_text)) {
+            links.push(Link {
+                kind,
+                target,
+                line: index + 1,
+                offset,
+            });
+        }
+    }
+    links
+}
+
+/// `line` with the text of inline code spans blanked out, keeping every byte offset.
+fn without_code_spans(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut in_code = false;
+    for c in line.chars() {
+        if c == '`' {
+            in_code = !in_code;
+            text.push(' ');
+        } else if in_code {
+            text.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        } else {
+            text.push(c);
+        }
+    }
+    text
+}
+
+fn wiki_links(line: &str) -> impl Iterator<Item = (usize, LinkKind, String)> + '_ {
+    line.match_indices("[[").filter_map(|(start, _)| {
+        let inner = &line[start + 2..];
+        let inner = &inner[..inner.find("]]")?];
+        let target = inner.split(['|', '#']).next().unwrap_or_default().trim();
+        (!target.is_empty()).then(|| (start, LinkKind::Wiki, target.to_string()))
+    })
+}
+
+fn markdown_links(line: &str) -> impl Iterator<Item = (usize, LinkKind, String)> + '_ {
+    line.match_indices("](").filter_map(|(start, _)| {
+        // Skip the `]]` + `(` of a wiki link followed by parentheses.
+        let text_start = line[..start].rfind('[')?;
+        if line[..text_start].ends_with('[') {
+            return None;
+        }
+        let rest = &line[start + 2..];
+        let target = match rest.strip_prefix('<') {
+            Some(rest) => &rest[..rest.find('>')?],
+            None => {
+                let end = rest.find(|c: char| c == ')' || c.is_whitespace())?;
+                &rest[..end]
+            }
+        };
+        let target = target.split(['#', '?']).next().unwrap_or_default();
+        if target.is_empty() || target.contains("://") || target.starts_with("mailto:") {
+            return None;
+        }
+        let (start, kind) = if line[..text_start].ends_with('!') {
+            (text_start - 1, LinkKind::Image)
+        } else {
+            (text_start, LinkKind::Markdown)
+        };
+        Some((start, kind, percent_decode(target)))
+    })
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| text.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Resolves links against the files of the note tree.
+pub struct Resolver {
+    files: HashSet<String>,
+    /// The paths of the files with each lowercase name, with and without a `.md` extension.
+    by_name: HashMap<String, Vec<String>>,
+}
+
+impl Resolver {
+    /// A resolver over `files`, the full paths of every file below `notes/`.
+    pub fn new(files: impl IntoIterator<Item = String>) -> Self {
+        let files: HashSet<String> = files.into_iter().collect();
+        let mut by_name: HashMap<String, Vec<String>> = HashMap::new();
+        for path in &files {
+            let name = path.rsplit('/').next().unwrap_or_default().to_lowercase();
+            if let Some(stem) = name.strip_suffix(".md") {
+                by_name.entry(stem.to_string()).or_default().push(path.clone());
+            }
+            by_name.entry(name).or_default().push(path.clone());
+        }
+        Self { files, by_name }
+    }
+
+    /// The path of the file `link`, written in the note at `source`, points to, or `None` if
+    /// the link is broken.
+    pub fn resolve(&self, source: &str, link: &Link) -> Option<String> {
+        match link.kind {
+            LinkKind::Wiki => self.resolve_wiki(source, &link.target),
+            LinkKind::Markdown | LinkKind::Image => self.resolve_markdown(source, &link.target),
+        }
+    }
+
+    /// A wiki link names a note by its file name, with or without `.md`, or by its path below
+    /// `notes/`. When several notes have the name, the one closest to `source` wins.
+    fn resolve_wiki(&self, source: &str, target: &str) -> Option<String> {
+        let target = target.trim_matches('/');
+        let exact = normalize("notes", target)?;
+        if let Some(path) = self.existing(&exact) {
+            return Some(path);
+        }
+        let (dir, name) = match target.rsplit_once('/') {
+            Some((dir, name)) => (Some(format!("/{}/", dir.to_lowercase())), name),
+            None => (None, target),
+        };
+        let source_dir = parent(source);
+        self.by_name
+            .get(&name.to_lowercase())?
+            .iter()
+            .filter(|path| match &dir {
+                Some(dir) => format!("/{}/", parent(path).to_lowercase()).ends_with(dir.as_str()),
+                None => true,
+            })
+            .min_by_key(|path| (parent(path) != source_dir, path.matches('/').count(), path.as_str()))
+            .cloned()
+    }
+
+    /// A Markdown link is a path relative to `source`, or to `notes/` if it starts with `/`.
+    fn resolve_markdown(&self, source: &str, target: &str) -> Option<String> {
+        let path = match target.strip_prefix('/') {
+            Some(target) => normalize("notes", target)?,
+            None => normalize(parent(source), target)?,
+        };
+        self.existing(&path)
+            .or_else(|| self.existing(&format!("{}/README.md", path)))
+    }
+
+    /// `path` or `path.md`, whichever is a file.
+    fn existing(&self, path: &str) -> Option<String> {
+        [path.to_string(), format!("{}.md", path)]
+            .into_iter()
+            .find(|path| self.files.contains(path))
+    }
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Joins `target` to `dir`, resolving `.` and `..`. `None` if the result leaves `notes/`.
+fn normalize(dir: &str, target: &str) -> Option<String> {
+    let mut parts: Vec<&str> = dir.split('/').filter(|part| !part.is_empty()).collect();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    (parts.first() == Some(&"notes") && parts.len() > 1).then(|| parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(content: &str) -> Vec<(LinkKind, String)> {
+        extract(content)
+            .into_iter()
+            .map(|link| (link.kind, link.target))
+            .collect()
+    }
+
+    #[test]
+    fn extracts_note_links() {
+        let content = "See [[Plans|the plans]] and [todo](../todo.md#next).\n![chart](assets/chart%201.png)";
+        assert_eq!(
+            targets(content),
+            [
+                (LinkKind::Wiki, "Plans".to_string()),
+                (LinkKind::Markdown, "../todo.md".to_string()),
+                (LinkKind::Image, "assets/chart 1.png".to_string()),
+            ]
+        );
+        let links = extract(content);
+        assert_eq!((links[0].offset, links[1].offset), (4, 28));
+        assert_eq!((links[2].line, links[2].offset), (2, 0));
+    }
+
+    #[test]
+    fn skips_code_and_external_links() {
+        let content = "`[[code]]` [site](https://example.com) [mail](mailto:a@b.c) [top](#top)\n```\n[[fenced]]\n```";
+        assert!(extract(content).is_empty());
+    }
+
+    fn resolver() -> Resolver {
+        Resolver::new(
+            [
+                "notes/README.md",
+                "notes/plans.md",
+                "notes/work/plans.md",
+                "notes/work/README.md",
+                "notes/work/assets/chart.png",
+            ]
+            .map(String::from),
+        )
+    }
+
+    fn link(kind: LinkKind, target: &str) -> Link {
+        Link {
+            kind,
+            target: target.to_string(),
+            line: 1,
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn resolves_wiki_links_by_name_closest_first() {
+        let resolver = resolver();
+        let plans = link(LinkKind::Wiki, "Plans");
+        assert_eq!(
+            resolver.resolve("notes/work/todo.md", &plans).as_deref(),
+            Some("notes/work/plans.md")
+        );
+        assert_eq!(
+            resolver.resolve("notes/other/todo.md", &plans).as_deref(),
+            Some("notes/plans.md")
+        );
+        let by_path = link(LinkKind::Wiki, "work/plans");
+        assert_eq!(
+            resolver.resolve("notes/README.md", &by_path).as_deref(),
+            Some("notes/work/plans.md")
+        );
+        assert_eq!(
+            resolver.resolve("notes/README.md", &link(LinkKind::Wiki, "missing")),
+            None
+        );
+    }
+
+    #[test]
+    fn resolves_markdown_links_relative_to_the_note() {
+        let resolver = resolver();
+        let source = "notes/work/todo.md";
+        let cases = [
+            (LinkKind::Markdown, "plans", Some("notes/work/plans.md")),
+            (LinkKind::Markdown, "../plans.md", Some("notes/plans.md")),
+            (LinkKind::Markdown, "/work", Some("notes/work/README.md")),
+            (LinkKind::Image, "assets/chart.png", Some("notes/work/assets/chart.png")),
+            (LinkKind::Markdown, "../../outside.md", None),
+        ];
+        for (kind, target, expected) in cases {
+            assert_eq!(
+                resolver.resolve(source, &link(kind, target)).as_deref(),
+                expected,
+                "{}",
+                target
+            );
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use serde::Serialize;
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+/// A region both sides changed differently.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ConflictHunk {
+    /// 1-based line of the `<<<<<<<` marker in the merged content.
+    pub line: usize,
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Merge {
+    /// The merged text. Conflicting regions are wrapped in `<<<<<<<`/`=======`/`>>>>>>>` markers.
+    pub content: String,
+    pub conflicts: Vec<ConflictHunk>,
+}
+
+impl Merge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    fn push_region(&mut self, base: &[&str], ours: &[&str], theirs: &[&str]) {
+        if ours == theirs || theirs == base {
+            self.content.extend(ours.iter().copied());
+        } else if ours == base {
+            self.content.extend(theirs.iter().copied());
+        } else {
+            self.push_conflict(base.concat(), ours.concat(), theirs.concat());
+        }
+    }
+
+    fn push_conflict(&mut self, base: String, ours: String, theirs: String) {
+        if !self.content.is_empty() && !self.content.ends_with('\n') {
+            self.content.push('\n');
+        }
+        self.conflicts.push(ConflictHunk {
+            line: self.content.lines().count() + 1,
+            base,
+            ours: ours.clone(),
+            theirs: theirs.clone(),
+        });
+        self.content.push_str("<<<<<<< ours\n");
+        push_line_terminated(&mut self.content, &ours);
+        self.content.push_str("=======\n");
+        push_line_terminated(&mut self.content, &theirs);
+        self.content.push_str(">>>>>>> theirs\n");
+    }
+}
+
+/// Line-based three-way merge of `ours` and `theirs`, both derived from `base`.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Merge {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let ours_by_base = matching_lines(&base, &ours);
+    let theirs_by_base = matching_lines(&base, &theirs);
+
+    let mut merge = Merge {
+        content: String::new(),
+        conflicts: Vec::new(),
+    };
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        // The next base line that is unchanged on both sides splits off the region before it.
+        let stable = (b..base.len()).find_map(|i| Some((i, ours_by_base[i]?, theirs_by_base[i]?)));
+        let (b_end, o_end, t_end) = stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        merge.push_region(&base[b..b_end], &ours[o..o_end], &theirs[t..t_end]);
+
+        match stable {
+            Some((b_stable, o_stable, t_stable)) => {
+                merge.content.push_str(base[b_stable]);
+                (b, o, t) = (b_stable + 1, o_stable + 1, t_stable + 1);
+            }
+            None => return merge,
+        }
+    }
+}
+
+fn push_line_terminated(content: &mut String, text: &str) {
+    content.push_str(text);
+    if !text.is_empty() && !text.ends_with('\n') {
+        content.push('\n');
+    }
+}
+
+/// For every line of `base`, the index of the same line in `other` if it was left unchanged.
+fn matching_lines(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    for op in capture_diff_slices(Algorithm::Myers, base, other) {
+        if let DiffOp::Equal {
+            old_index,
+            new_index,
+            len,
+        } = op
+        {
+            for offset in 0..len {
+                matches[old_index + offset] = Some(new_index + offset);
+            }
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_edits_to_different_lines() {
+        let merge = merge("a\nb\nc\n", "A\nb\nc\n", "a\nb\nC\n");
+        assert!(merge.is_clean());
+        assert_eq!(merge.content, "A\nb\nC\n");
+    }
+
+    #[test]
+    fn takes_identical_edits_once() {
+        let merge = merge("a\nb\n", "a\nB\n", "a\nB\n");
+        assert!(merge.is_clean());
+        assert_eq!(merge.content, "a\nB\n");
+    }
+
+    #[test]
+    fn marks_overlapping_edits() {
+        let merge = merge("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+        assert_eq!(
+            merge.content,
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+        assert_eq!(merge.conflicts.len(), 1);
+        let hunk = &merge.conflicts[0];
+        assert_eq!(hunk.line, 2);
+        assert_eq!(
+            (hunk.base.as_str(), hunk.ours.as_str(), hunk.theirs.as_str()),
+            ("b\n", "ours\n", "theirs\n")
+        );
+    }
+
+    #[test]
+    fn terminates_the_last_line_before_a_marker() {
+        let merge = merge("a\nb", "a\nours", "a\ntheirs");
+        assert_eq!(
+            merge.content,
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n"
+        );
+    }
+
+    #[test]
+    fn merges_against_an_empty_base() {
+        assert_eq!(merge("", "same\n", "same\n").content, "same\n");
+        assert!(!merge("", "ours\n", "theirs\n").is_clean());
+    }
+}
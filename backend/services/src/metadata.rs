@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Metadata declared in a note's YAML front matter, the block between `---` lines at the very
+/// start of the file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NoteMetadata {
+    #[serde(
+        default,
+        deserialize_with = "optional_scalar",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub title: Option<String>,
+    /// Written either as a YAML list or as a comma-separated string.
+    #[serde(default, deserialize_with = "string_or_list", skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "optional_scalar",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "optional_scalar",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated: Option<String>,
+    #[serde(default, deserialize_with = "string_or_list", skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Any other fields, kept as they were declared.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// A YAML scalar taken as the text it was written as, so that `title: 2024` or `tags: [1, true]`
+/// are read as strings rather than making the whole front matter invalid.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    String(String),
+    Bool(bool),
+    Number(serde_yaml::Number),
+}
+
+impl From<Scalar> for String {
+    fn from(scalar: Scalar) -> Self {
+        match scalar {
+            Scalar::String(value) => value,
+            Scalar::Bool(value) => value.to_string(),
+            Scalar::Number(value) => value.to_string(),
+        }
+    }
+}
+
+fn optional_scalar<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<Scalar>::deserialize(deserializer)?.map(String::from))
+}
+
+fn string_or_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(Scalar),
+        List(Vec<Scalar>),
+    }
+    Ok(match Option::<StringOrList>::deserialize(deserializer)? {
+        Some(StringOrList::String(value)) => String::from(value)
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Some(StringOrList::List(values)) => values.into_iter().map(String::from).collect(),
+        None => Vec::new(),
+    })
+}
+
+/// Splits `content` into its front matter and the body after it. The body is returned exactly as
+/// written. Content without front matter, or whose front matter is not valid YAML metadata, is
+/// all body.
+pub fn parse(content: &str) -> (Option<NoteMetadata>, &str) {
+    let Some((yaml, body)) = split(content) else {
+        return (None, content);
+    };
+    let metadata = if yaml.trim().is_empty() {
+        Some(NoteMetadata::default())
+    } else {
+        serde_yaml::from_str(yaml).ok()
+    };
+    match metadata {
+        Some(metadata) => (Some(metadata), body),
+        None => (None, content),
+    }
+}
+
+/// Replaces the front matter of `content` with `metadata`, or adds it when there is none, and
+/// leaves the body untouched. Empty metadata removes the front matter.
+pub fn apply(content: &str, metadata: &NoteMetadata) -> String {
+    let body = match split(content) {
+        Some((_, body)) => body,
+        None => content,
+    };
+    if *metadata == NoteMetadata::default() {
+        return body.to_string();
+    }
+    let yaml = serde_yaml::to_string(metadata).unwrap_or_default();
+    format!("---\n{}---\n{}", yaml, body)
+}
+
+/// Splits off the raw YAML between the opening `---` line and the closing `---` or `...` line.
+fn split(content: &str) -> Option<(&str, &str)> {
+    let rest = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end_matches(['\r', '\n']), "---" | "...") {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_front_matter() {
+        let content = "---\ntitle: Plans\ntags: work, q3\nstatus: draft\n---\n# Plans\n";
+        let (metadata, body) = parse(content);
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Plans"));
+        assert_eq!(metadata.tags, ["work", "q3"]);
+        assert_eq!(metadata.extra["status"], "draft");
+        assert_eq!(body, "# Plans\n");
+    }
+
+    #[test]
+    fn accepts_tag_lists_and_dots() {
+        let (metadata, body) = parse("---\ntags: [a, b]\n...\nbody");
+        assert_eq!(metadata.unwrap().tags, ["a", "b"]);
+        assert_eq!(body, "body");
+    }
+
+    #[test]
+    fn reads_numbers_and_booleans_as_text() {
+        let content = "---\ntitle: 2024\ncreated: 2024-01-31 10:00:00\nupdated: 1.5\n\
+                       tags: [1, true, work]\naliases: false\n---\nbody";
+        let (metadata, body) = parse(content);
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("2024"));
+        assert_eq!(metadata.created.as_deref(), Some("2024-01-31 10:00:00"));
+        assert_eq!(metadata.updated.as_deref(), Some("1.5"));
+        assert_eq!(metadata.tags, ["1", "true", "work"]);
+        assert_eq!(metadata.aliases, ["false"]);
+        assert_eq!(body, "body");
+    }
+
+    #[test]
+    fn reads_numbers_and_booleans_sent_as_json() {
+        let metadata: NoteMetadata = serde_json::from_str(r#"{"title": 7, "tags": [2, false]}"#).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("7"));
+        assert_eq!(metadata.tags, ["2", "false"]);
+    }
+
+    #[test]
+    fn treats_invalid_front_matter_as_body() {
+        for content in [
+            "# No front matter\n",
+            "---\nnot closed\n",
+            "---\ntitle: [unclosed\n---\nbody",
+        ] {
+            assert_eq!(parse(content), (None, content));
+        }
+    }
+
+    #[test]
+    fn replaces_front_matter() {
+        let metadata = NoteMetadata {
+            title: Some("New".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            apply("---\ntitle: Old\n---\nbody\n", &metadata),
+            "---\ntitle: New\n---\nbody\n"
+        );
+        assert_eq!(apply("body\n", &metadata), "---\ntitle: New\n---\nbody\n");
+    }
+
+    #[test]
+    fn removes_empty_front_matter() {
+        assert_eq!(
+            apply("---\ntitle: Old\n---\nbody\n", &NoteMetadata::default()),
+            "body\n"
+        );
+    }
+}
@@ -1,23 +1,81 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
 use anyhow::Result;
-use async_recursion::async_recursion;
 use futures::future::join_all;
 use serde::{Deserialize, Serialize};
 
-use github_service::{GitHubService, GitHubServiceError};
+use github_service::GitHubServiceError;
+
+use crate::diff::{self, NoteDiff};
+use crate::links::{Backlink, BrokenLinks};
+use crate::merge::{self, ConflictHunk};
+use crate::metadata::{self, NoteMetadata};
+use crate::query::Query;
+use crate::search::{IndexedNote, SearchHit, SearchIndex, MAX_RESULTS};
+use crate::store::{self, list_files, EntryKind, GitHubStore, NoteStore, StoreChange, StoreEntry};
+use crate::tags::{self, TagCount, TaggedNote};
 
 #[derive(Debug, thiserror::Error)]
 pub enum NoteServiceError {
     #[error("Note already exists")]
     NoteAlreadyExists,
+    #[error("Note not found")]
+    NotFound,
+    #[error("Note has been modified since it was read (current revision {})", .0.current_revision)]
+    Conflict(NoteConflict),
     #[error("GitHub API error: {0}")]
     GitHub(String),
+    #[error("I/O error: {0}")]
+    Io(String),
+    #[error("Git error: {0}")]
+    Git(String),
+    #[error("{0} is not supported by this storage backend")]
+    Unsupported(String),
+    #[error("Invalid search query: {0}")]
+    InvalidQuery(String),
+    #[error("Invalid attachment: {0}")]
+    InvalidAttachment(String),
+    /// A path the operation cannot use, such as moving a category into itself.
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+    /// The file exists, but the backend cannot return its content, e.g. a file over the size
+    /// limit of the GitHub API.
+    #[error("Content of {0} is not available")]
+    ContentUnavailable(String),
+    /// GitHub kept rejecting requests because of a rate limit. `reset_at` is when the limit
+    /// resets, as an RFC 3339 timestamp, if GitHub said.
+    #[error("GitHub rate limit exceeded{}", .reset_at.as_ref().map(|at| format!(", retry after {}", at)).unwrap_or_default())]
+    RateLimited { reset_at: Option<String> },
     #[error("An internal error occurred: {0}")]
     Anyhow(String),
 }
 
 impl From<anyhow::Error> for NoteServiceError {
     fn from(err: anyhow::Error) -> Self {
-        NoteServiceError::Anyhow(err.to_string())
+        // Keep the original error when it went through one of the `anyhow::Result` methods.
+        match err.downcast::<NoteServiceError>() {
+            Ok(err) => err,
+            Err(err) => NoteServiceError::Anyhow(err.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for NoteServiceError {
+    fn from(err: std::io::Error) -> Self {
+        NoteServiceError::Io(err.to_string())
+    }
+}
+
+impl From<octocrab::Error> for NoteServiceError {
+    fn from(err: octocrab::Error) -> Self {
+        NoteServiceError::GitHub(err.to_string())
+    }
+}
+
+impl From<git2::Error> for NoteServiceError {
+    fn from(err: git2::Error) -> Self {
+        NoteServiceError::Git(err.message().to_string())
     }
 }
 
@@ -31,197 +89,940 @@ impl From<GitHubServiceError> for NoteServiceError {
     }
 }
 
+/// The state of a note that changed since the client read it, returned so the client can merge.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct NoteConflict {
+    pub current_revision: String,
+    pub current_content: String,
+    /// The three-way merge of both edits, with conflict markers around the `hunks`. Absent when
+    /// the revision the client started from is no longer available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merged_content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hunks: Vec<ConflictHunk>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Note {
     pub id: String,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+    /// The revision `content` was read at, to send back with `UpdateNote`. In a listing, the
+    /// note's current revision where the backend reports it, e.g. to tell whether a cached copy
+    /// is still current.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// The note's front matter. `content` still includes it as written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<NoteMetadata>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub children: Option<Vec<Note>>,
+    /// For a category, the paths below it that could not be loaded, so `children` may be
+    /// incomplete.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ListWarning>,
+}
+
+/// A commit that changed a note.
+#[derive(Serialize, Debug, Clone)]
+pub struct NoteCommit {
+    pub sha: String,
+    pub author: String,
+    pub email: String,
+    /// RFC 3339 timestamp of the commit.
+    pub date: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DeleteFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// The outcome of `NoteService::delete_tree`. Files in `failed` are left in place.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DeleteReport {
+    pub deleted: Vec<String>,
+    pub failed: Vec<DeleteFailure>,
+}
+
+/// A category that could not be listed, or a note that could not be read, while listing notes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListWarning {
+    pub path: String,
+    pub error: String,
+}
+
+/// The outcome of `NoteService::get_all_notes`. The notes below a path in `warnings` are missing
+/// or incomplete because loading them failed, not because they were deleted.
+#[derive(Serialize, Debug, Default)]
+pub struct NoteTree {
+    pub notes: Vec<Note>,
+    pub warnings: Vec<ListWarning>,
 }
 
 #[derive(Deserialize)]
 pub struct CreateNote {
     pub path: String,
     pub content: String,
+    /// Front matter to write at the start of `content`, replacing any it already has.
+    #[serde(default)]
+    pub metadata: Option<NoteMetadata>,
+}
+
+#[derive(Deserialize)]
+pub struct MoveNote {
+    /// The new path of the note or category, relative to `notes/`.
+    pub to: String,
+}
+
+#[derive(Deserialize)]
+pub struct CopyNote {
+    /// The path of the copy, relative to `notes/`.
+    pub to: String,
+    /// Overwrite notes that already exist at the target instead of failing.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// The folder of a category that holds the attachments of its notes.
+pub const ATTACHMENTS_DIR: &str = "assets";
+
+/// A binary file, such as an image or a PDF, stored next to the notes of a category.
+#[derive(Serialize, Debug, Clone)]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    /// The relative link to the attachment from a note in its category, e.g.
+    /// `assets/diagram.png`.
+    pub link: String,
+    pub size: usize,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreNote {
+    /// The commit SHA, from the note's history, to restore the note to.
+    pub rev: String,
 }
 
 #[derive(Deserialize)]
 pub struct UpdateNote {
     pub content: String,
+    /// The revision the client last read. When set, the update is rejected with
+    /// `NoteServiceError::Conflict` if the note has changed since.
+    #[serde(default)]
+    pub revision: Option<String>,
+    /// Front matter to write at the start of `content`, replacing any it already has. The rest
+    /// of `content` is saved exactly as sent.
+    #[serde(default)]
+    pub metadata: Option<NoteMetadata>,
+}
+
+/// A note as `NoteService::update_note` saved it.
+#[derive(Serialize, Debug, Clone)]
+pub struct SavedNote {
+    /// The revision to send with the next update.
+    pub revision: String,
+    /// The saved content, which differs from the content sent when edits made elsewhere were
+    /// merged into it.
+    pub content: String,
+}
+
+/// A group of note changes that `NoteService::commit_batch` writes as a single commit, so the
+/// repository never ends up with only part of them.
+#[derive(Debug, Default)]
+pub struct NoteBatch {
+    changes: Vec<StoreChange>,
+}
+
+impl NoteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the note at `id`, or overwrites it if it exists.
+    pub fn put(&mut self, id: &str, content: impl Into<String>) -> &mut Self {
+        self.changes.push(StoreChange::Put {
+            path: format!("notes/{}", id),
+            content: content.into(),
+        });
+        self
+    }
+
+    /// Creates or overwrites the file at `id` with `content`, which need not be text.
+    pub fn put_bytes(&mut self, id: &str, content: Vec<u8>) -> &mut Self {
+        self.changes.push(StoreChange::file(format!("notes/{}", id), content));
+        self
+    }
+
+    /// Copies the file at `from` to `to`. `revision` is the revision `from` was listed with, if
+    /// known, so the store can copy it without reading it.
+    pub fn copy(&mut self, from: &str, to: &str, revision: Option<String>) -> &mut Self {
+        self.changes.push(StoreChange::Copy {
+            path: format!("notes/{}", to),
+            from: format!("notes/{}", from),
+            revision,
+        });
+        self
+    }
+
+    pub fn delete(&mut self, id: &str) -> &mut Self {
+        self.changes.push(StoreChange::Delete {
+            path: format!("notes/{}", id),
+        });
+        self
+    }
+
+    /// Rejects the whole batch unless the file at `id` is still at `revision`, or with `None`,
+    /// still does not exist (see `StoreChange::Expect`).
+    pub fn expect(&mut self, id: &str, revision: Option<String>) -> &mut Self {
+        self.changes.push(StoreChange::Expect {
+            path: format!("notes/{}", id),
+            revision,
+        });
+        self
+    }
+
+    /// Whether the batch writes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.changes
+            .iter()
+            .all(|change| matches!(change, StoreChange::Expect { .. }))
+    }
 }
 
-pub struct NoteService {
-    github_service: GitHubService,
+pub struct NoteService<S = Box<dyn NoteStore>> {
+    store: S,
     app_identifier: String,
+    search_index: Arc<SearchIndex>,
 }
 
-impl NoteService {
+impl NoteService<GitHubStore> {
     pub fn new(github_token: String, repo_name: String, app_identifier: String) -> Result<Self, NoteServiceError> {
-        let parts: Vec<&str> = repo_name.split('/').collect();
-        if parts.len() != 2 {
-            return Err(NoteServiceError::Anyhow(
-                "Invalid repo_name format. Expected 'owner/name'".to_string(),
-            ));
-        }
-        let owner = parts[0].to_string();
-        let repo = parts[1].to_string();
+        let store = GitHubStore::new(github_token, repo_name)?;
+        Ok(Self::with_store(store, app_identifier))
+    }
+}
 
-        let github_service = GitHubService::new(github_token, owner, repo);
+impl NoteService<Box<dyn NoteStore>> {
+    /// Opens the notes configured by `notes_repo`, either a GitHub `owner/name` repository
+    /// or a local directory (see `NotesSource::parse`). The token is only needed for GitHub.
+    pub fn open(
+        github_token: Option<String>,
+        notes_repo: String,
+        app_identifier: String,
+    ) -> Result<Self, NoteServiceError> {
+        let store = store::open(github_token, &notes_repo)?;
+        Ok(Self::with_store(store, app_identifier))
+    }
+}
 
-        Ok(Self {
-            github_service,
-            app_identifier,
+/// `content` with its front matter replaced by `metadata`, if given.
+fn with_metadata(content: &str, metadata: Option<&NoteMetadata>) -> String {
+    match metadata {
+        Some(metadata) => metadata::apply(content, metadata),
+        None => content.to_string(),
+    }
+}
+
+/// Fails with `InvalidPath` when `to` is `from` or below it, where a move or copy of `from`
+/// cannot go.
+fn check_outside(from: &str, to: &str, action: &str) -> Result<(), NoteServiceError> {
+    if to == from || to.starts_with(&format!("{}/", from)) {
+        return Err(NoteServiceError::InvalidPath(format!(
+            "cannot {} {} into itself",
+            action, from
+        )));
+    }
+    Ok(())
+}
+
+/// Whether the file at `path` is a note or other text file, by its extension. Other files, such
+/// as attachments, are indexed without their content.
+fn is_text_file(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            ["md", "markdown", "txt"]
+                .iter()
+                .any(|text| extension.eq_ignore_ascii_case(text))
         })
+}
+
+/// The notes below `parent`, with the categories filled in from `children`, the entries listed
+/// below each directory.
+fn build_tree(
+    parent: &str,
+    children: &HashMap<&str, Vec<&StoreEntry>>,
+    metadata: &mut HashMap<String, Option<NoteMetadata>>,
+) -> Vec<Note> {
+    let Some(entries) = children.get(parent) else {
+        return Vec::new();
+    };
+    entries
+        .iter()
+        .map(|entry| match entry.kind {
+            EntryKind::Dir => Note {
+                id: entry.path.clone(),
+                name: entry.name.clone(),
+                content: None,
+                revision: None,
+                metadata: None,
+                children: Some(build_tree(&entry.path, children, metadata)),
+                warnings: Vec::new(),
+            },
+            EntryKind::File => Note {
+                id: entry.path.clone(),
+                name: entry.name.clone(),
+                content: None,
+                revision: entry.revision.clone(),
+                metadata: metadata.remove(&entry.path).flatten(),
+                children: None,
+                warnings: Vec::new(),
+            },
+        })
+        .collect()
+}
+
+impl<S: NoteStore> NoteService<S> {
+    pub fn with_store(store: S, app_identifier: String) -> Self {
+        Self {
+            store,
+            app_identifier,
+            search_index: Arc::new(SearchIndex::new()),
+        }
     }
 
-    pub async fn get_all_notes(&self) -> Result<Vec<Note>> {
-        self.get_notes_recursive("notes/").await.map_err(|e| e.into())
+    /// Uses `search_index`, shared with other services over the same notes, instead of a fresh
+    /// index that is rebuilt on the first search.
+    pub fn with_search_index(mut self, search_index: Arc<SearchIndex>) -> Self {
+        self.search_index = search_index;
+        self
     }
 
-    #[async_recursion]
-    async fn get_notes_recursive(&self, path: &str) -> Result<Vec<Note>, NoteServiceError> {
-        let content_items = self.github_service.get_content_items(path).await?;
-        let futures = content_items.into_iter().map(|item| async move {
-            let id = item.path.clone();
-            let name = item.name.clone();
+    /// Searches the note tree with a query in the syntax of `Query`, bringing the search index up
+    /// to date with the store first. An empty query finds nothing.
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchHit>, NoteServiceError> {
+        let query = Query::parse(query)?;
+        if query == Query::All {
+            return Ok(Vec::new());
+        }
+        self.refresh_search_index().await?;
 
-            if item.r#type == "dir" {
-                let children = self.get_notes_recursive(&item.path).await?;
-                Ok(Note {
-                    id,
-                    name,
-                    content: None,
-                    children: Some(children),
-                })
-            } else {
-                Ok(Note {
-                    id,
-                    name,
-                    content: None,
-                    children: None,
-                })
+        let mut matched = Vec::new();
+        let mut undecided = Vec::new();
+        for path in list_files(&self.store, "notes/")
+            .await?
+            .into_iter()
+            .map(|file| file.path)
+        {
+            match self.search_index.matches(&query, &path, None) {
+                Some(true) => matched.push(path),
+                Some(false) => {}
+                None => undecided.push(path),
             }
-        });
-        let notes: Vec<Result<Note, NoteServiceError>> = join_all(futures).await;
-        let notes: Vec<Note> = notes.into_iter().filter_map(Result::ok).collect();
-        Ok(notes)
-    }
+        }
 
-    pub async fn get_note(&self, id: &str) -> Result<Option<Note>> {
-        let path = format!("notes/{}", id);
-        match self.github_service.get_content_items(&path).await {
-            Ok(content) => {
-                if let Some(item) = content.into_iter().next() {
-                    let note = if item.r#type == "dir" {
-                        let children = self.get_notes_recursive(&item.path).await?;
-                        Note {
-                            id: item.path,
-                            name: item.name,
-                            content: None,
-                            children: Some(children),
-                        }
-                    } else {
-                        let content = self.get_note_content(&item.path).await?;
-                        Note {
-                            id: item.path,
-                            name: item.name,
-                            content,
-                            children: None,
-                        }
-                    };
-                    Ok(Some(note))
-                } else {
-                    Ok(None)
+        // Only look up modification dates when the rest of the query cannot decide, all at once.
+        if !undecided.is_empty() {
+            let dates = self.store.last_modified_all(&undecided).await?;
+            for path in undecided {
+                let modified = dates.get(&path).and_then(|date| {
+                    chrono::DateTime::parse_from_rfc3339(date)
+                        .ok()
+                        .map(|date| date.date_naive())
+                });
+                self.search_index.set_modified(&path, modified);
+                if self.search_index.matches(&query, &path, Some(modified)) == Some(true) {
+                    matched.push(path);
                 }
             }
-            Err(e) => {
-                if let Some(octocrab::Error::GitHub { source, .. }) = e.downcast_ref::<octocrab::Error>() {
-                    if source.status_code == 404 {
-                        return Ok(None);
+        }
+        Ok(self.search_index.rank(&query, matched, MAX_RESULTS))
+    }
+
+    /// Every tag used in the notes, with the number of notes tagged with it or a tag nested below
+    /// it. Tags come from front matter and from `#tags` in note bodies.
+    pub async fn get_tags(&self) -> Result<Vec<TagCount>, NoteServiceError> {
+        self.refresh_search_index().await?;
+        Ok(self.search_index.tags())
+    }
+
+    /// The notes tagged with `tag` or a tag nested below it, so `project` also finds the notes
+    /// tagged `project/alpha`.
+    pub async fn get_notes_by_tag(&self, tag: &str) -> Result<Vec<TaggedNote>, NoteServiceError> {
+        let Some(tag) = tags::normalize(tag) else {
+            return Ok(Vec::new());
+        };
+        self.refresh_search_index().await?;
+        Ok(self.search_index.tagged(&tag))
+    }
+
+    /// The notes that link to the note `id`, through `[[wiki links]]` or relative Markdown links,
+    /// with the lines the links are written in.
+    pub async fn get_backlinks(&self, id: &str) -> Result<Vec<Backlink>, NoteServiceError> {
+        let path = format!("notes/{}", id.trim_matches('/'));
+        self.refresh_search_index().await?;
+        if !self.search_index.contains(&path) {
+            return Err(NoteServiceError::NotFound);
+        }
+        Ok(self.search_index.backlinks(&path))
+    }
+
+    /// Every link, image and `[[wiki link]]` in the notes that points to a note or attachment that
+    /// does not exist, grouped by the note it is written in. The whole tree is checked against
+    /// the search index, which is brought up to date first.
+    pub async fn check_links(&self) -> Result<Vec<BrokenLinks>, NoteServiceError> {
+        self.refresh_search_index().await?;
+        Ok(self.search_index.broken_links())
+    }
+
+    /// The text of the file at `path`, for indexing. Missing files, files that are not text, such
+    /// as attachments, and files whose content is unavailable are empty. Attachments are not read
+    /// at all.
+    async fn read_text(&self, path: &str) -> Result<String, NoteServiceError> {
+        if !is_text_file(path) {
+            return Ok(String::new());
+        }
+        match self.store.read_bytes(path).await {
+            Ok(content) => Ok(content
+                .and_then(|content| String::from_utf8(content).ok())
+                .unwrap_or_default()),
+            Err(NoteServiceError::ContentUnavailable(_)) => Ok(String::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Brings the search index up to date with the store, which other services or other clients
+    /// may have changed. Called before every query that uses the index. Nothing is read when the
+    /// store is still at the commit the index was built from; otherwise only notes whose revision
+    /// changed are.
+    async fn refresh_search_index(&self) -> Result<(), NoteServiceError> {
+        let head_commit = self.store.head_commit().await?;
+        if head_commit.is_some() && head_commit == self.search_index.commit() {
+            return Ok(());
+        }
+
+        let mut notes = Vec::new();
+        for file in list_files(&self.store, "notes/").await? {
+            let revision = match file.revision {
+                Some(revision) => revision,
+                None => self.store.revision(&file.path).await?,
+            };
+            let content = match self.search_index.content_at(&file.path, &revision) {
+                Some(content) => content,
+                None => self.read_text(&file.path).await?,
+            };
+            notes.push(IndexedNote {
+                path: file.path,
+                revision: Some(revision),
+                content,
+            });
+        }
+        self.search_index.load(head_commit, notes);
+        Ok(())
+    }
+
+    /// Lists the tree of notes and categories. Categories and notes that fail to load are
+    /// reported in `warnings` instead of failing the whole listing.
+    pub async fn get_all_notes(&self) -> Result<NoteTree, NoteServiceError> {
+        self.get_notes_recursive("notes/", false).await
+    }
+
+    /// Like `get_all_notes`, but with the front matter of every note, e.g. to show titles
+    /// instead of file names. This reads every note.
+    pub async fn get_all_notes_with_metadata(&self) -> Result<NoteTree, NoteServiceError> {
+        self.get_notes_recursive("notes/", true).await
+    }
+
+    /// Builds the tree of notes and categories below `path` from a single listing of the store,
+    /// keeping the revision each file was listed at. If the store cannot list the tree at once,
+    /// it is listed one category at a time, unless it is rate limited, and the categories that
+    /// still fail are left empty. Only a failure to list `path` itself is an error.
+    async fn get_notes_recursive(&self, path: &str, with_metadata: bool) -> Result<NoteTree, NoteServiceError> {
+        let mut warnings = Vec::new();
+        let entries = match self.store.list_tree(path).await {
+            Ok(entries) => entries.unwrap_or_default(),
+            Err(e @ NoteServiceError::RateLimited { .. }) => return Err(e),
+            Err(_) => {
+                let mut failed = Vec::new();
+                let entries = store::walk_tree_partial(&self.store, path, &mut failed)
+                    .await?
+                    .unwrap_or_default();
+                warnings.extend(failed.into_iter().map(|(path, error)| ListWarning {
+                    path,
+                    error: error.to_string(),
+                }));
+                entries
+            }
+        };
+
+        let mut metadata = HashMap::new();
+        if with_metadata {
+            let futures = entries
+                .iter()
+                .filter(|entry| entry.kind == EntryKind::File)
+                .map(|entry| async move {
+                    let content = self.read_text(&entry.path).await;
+                    (entry.path.clone(), content.map(|content| metadata::parse(&content).0))
+                });
+            for (path, result) in join_all(futures).await {
+                match result {
+                    Ok(note_metadata) => {
+                        metadata.insert(path, note_metadata);
                     }
+                    // The note is still listed, only without its metadata.
+                    Err(e) => warnings.push(ListWarning {
+                        path,
+                        error: e.to_string(),
+                    }),
                 }
-                Err(e.into())
             }
         }
+
+        let mut children: HashMap<&str, Vec<&StoreEntry>> = HashMap::new();
+        for entry in &entries {
+            let parent = entry.path.rsplit_once('/').map_or("", |(parent, _)| parent);
+            children.entry(parent).or_default().push(entry);
+        }
+        Ok(NoteTree {
+            notes: build_tree(path.trim_end_matches('/'), &children, &mut metadata),
+            warnings,
+        })
     }
 
-    async fn get_note_content(&self, path: &str) -> Result<Option<String>> {
-        match self.github_service.get_content_items(path).await {
-            Ok(content) => Ok(content.into_iter().next().and_then(|item| item.decoded_content())),
-            Err(e) => {
-                if let Some(octocrab::Error::GitHub { source, .. }) = e.downcast_ref::<octocrab::Error>() {
-                    if source.status_code == 404 {
-                        return Ok(None);
-                    }
+    /// Gets the note at `id` with its content, or the category at `id` with the tree below it.
+    /// A category is returned even when parts of that tree could not be loaded; those are listed
+    /// in its `warnings`.
+    pub async fn get_note(&self, id: &str) -> Result<Option<Note>> {
+        let path = format!("notes/{}", id.trim_end_matches('/'));
+        let Some(mut entries) = self.store.list(&path).await? else {
+            return Ok(None);
+        };
+        // Listing a note yields the note itself, listing a category yields its children.
+        let note = match entries.as_slice() {
+            [] => return Ok(None),
+            [entry] if entry.kind == EntryKind::File && entry.path == path => {
+                let entry = entries.remove(0);
+                let content = self.store.read(&entry.path).await?;
+                let revision = match entry.revision {
+                    Some(revision) => revision,
+                    None => self.store.revision(&entry.path).await?,
+                };
+                Note {
+                    id: entry.path,
+                    name: entry.name,
+                    metadata: content.as_deref().and_then(|content| metadata::parse(content).0),
+                    content,
+                    revision: Some(revision),
+                    children: None,
+                    warnings: Vec::new(),
                 }
-                Err(e.into())
             }
+            _ => {
+                let tree = self.get_notes_recursive(&path, false).await?;
+                Note {
+                    name: path.rsplit('/').next().unwrap_or_default().to_string(),
+                    id: path,
+                    content: None,
+                    revision: None,
+                    metadata: None,
+                    children: Some(tree.notes),
+                    warnings: tree.warnings,
+                }
+            }
+        };
+        Ok(Some(note))
+    }
+
+    /// Lists the commits that changed the note or category at `id`, newest first.
+    pub async fn get_note_history(&self, id: &str) -> Result<Vec<NoteCommit>, NoteServiceError> {
+        let path = format!("notes/{}", id);
+        self.store.history(&path).await
+    }
+
+    /// Gets the note at `id` as it was in commit `rev`.
+    pub async fn get_note_at(&self, id: &str, rev: &str) -> Result<Option<Note>, NoteServiceError> {
+        let path = format!("notes/{}", id);
+        let content = self.store.read_at_commit(&path, rev).await?;
+        Ok(content.map(|content| Note {
+            name: path.rsplit('/').next().unwrap_or_default().to_string(),
+            id: path,
+            metadata: metadata::parse(&content).0,
+            content: Some(content),
+            revision: None,
+            children: None,
+            warnings: Vec::new(),
+        }))
+    }
+
+    /// Diffs the note at `id` between commits `from` and `to`, or between `from` and the current
+    /// content when `to` is `None`. A side where the note does not exist counts as empty.
+    pub async fn diff_note(&self, id: &str, from: &str, to: Option<&str>) -> Result<NoteDiff, NoteServiceError> {
+        let path = format!("notes/{}", id);
+        let old = self.store.read_at_commit(&path, from).await?;
+        let new = match to {
+            Some(to) => self.store.read_at_commit(&path, to).await?,
+            None => self.store.read(&path).await?,
+        };
+        if old.is_none() && new.is_none() {
+            return Err(NoteServiceError::NotFound);
         }
+        Ok(diff::diff(
+            &old.unwrap_or_default(),
+            &new.unwrap_or_default(),
+            &format!("{}@{}", path, from),
+            &format!("{}@{}", path, to.unwrap_or("current")),
+        ))
     }
 
-    pub async fn create_note(&self, payload: &CreateNote) -> Result<(), NoteServiceError> {
-        let full_path = format!("notes/{}", payload.path);
-        if self.github_service.note_exists(&full_path).await? {
-            return Err(NoteServiceError::NoteAlreadyExists);
+    /// Writes the content the note at `id` had in commit `rev` back as a new commit. Notes that
+    /// have been deleted since are recreated, along with their parent categories.
+    pub async fn restore_note(&self, id: &str, rev: &str) -> Result<(), NoteServiceError> {
+        let path = format!("notes/{}", id);
+        let content = self
+            .store
+            .read_at_commit(&path, rev)
+            .await?
+            .ok_or(NoteServiceError::NotFound)?;
+        let short_rev: String = rev.chars().take(7).collect();
+        let commit_message = format!("feat: restore note to {} by {}", short_rev, self.app_identifier);
+
+        if self.store.exists(&path).await? {
+            let revision = self.store.revision(&path).await?;
+            self.store.update(&path, &commit_message, &content, &revision).await?;
+            self.search_index.upsert(&path, &content);
+        } else {
+            let mut batch = NoteBatch::new();
+            self.add_missing_parents(&mut batch, id, &content).await?;
+            if !id.ends_with("README.md") {
+                batch.expect(id, None).put(id, content);
+            }
+            self.commit_batch(&commit_message, batch).await?;
         }
+        Ok(())
+    }
 
-        self.ensure_parent_directories_exist(&payload.path, &payload.content)
-            .await?;
+    /// Creates the note at `payload.path` together with the README of every category above it
+    /// that does not exist yet, all in one commit. Fails with `NoteAlreadyExists`, writing
+    /// nothing, if the note exists when the commit is made.
+    pub async fn create_note(&self, payload: &CreateNote) -> Result<(), NoteServiceError> {
+        let content = with_metadata(&payload.content, payload.metadata.as_ref());
+        let mut batch = NoteBatch::new();
+        batch.expect(&payload.path, None);
+        self.add_missing_parents(&mut batch, &payload.path, &content).await?;
 
         let is_readme = payload.path.ends_with("README.md");
         if !is_readme {
-            let commit_message = format!("feat: create new note by {}", self.app_identifier);
-            self.github_service
-                .create_file(&full_path, &commit_message, &payload.content)
-                .await?;
+            batch.put(&payload.path, content);
         }
 
+        let commit_message = format!("feat: create new note by {}", self.app_identifier);
+        self.commit_batch(&commit_message, batch).await
+    }
+
+    /// Writes every change in `batch` as a single commit, or one at a time on stores without
+    /// commits (see `NoteStore::commit_changes`).
+    pub async fn commit_batch(&self, message: &str, batch: NoteBatch) -> Result<(), NoteServiceError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.store.commit_changes(message, &batch.changes).await?;
+        for change in &batch.changes {
+            match change {
+                StoreChange::Put { path, content } => self.search_index.upsert(path, content),
+                // Indexed without content, so links to the file still resolve.
+                StoreChange::PutBytes { path, .. } => self.search_index.upsert(path, ""),
+                StoreChange::Delete { path } => self.search_index.remove(path),
+                StoreChange::Copy { path, from, .. } => self.search_index.copy(from, path),
+                StoreChange::Expect { .. } => {}
+            }
+        }
         Ok(())
     }
 
-    async fn ensure_parent_directories_exist(&self, path: &str, content: &str) -> Result<(), NoteServiceError> {
-        let path_obj = std::path::Path::new(path);
+    /// Adds a README to `batch` for every category above `id` that does not have one, expecting
+    /// it to still be missing when the batch is committed. When `id` is itself a category README,
+    /// it gets `content`.
+    async fn add_missing_parents(
+        &self,
+        batch: &mut NoteBatch,
+        id: &str,
+        content: &str,
+    ) -> Result<(), NoteServiceError> {
+        let path_obj = std::path::Path::new(id);
         if let Some(parent_path) = path_obj.parent() {
-            let mut cumulative_path = std::path::PathBuf::from("notes");
+            let mut cumulative_path = std::path::PathBuf::new();
             for component in parent_path.iter() {
                 cumulative_path.push(component);
                 let readme_path = cumulative_path.join("README.md");
-                let readme_path_str = readme_path.to_str().unwrap();
+                let readme_id = readme_path.to_str().unwrap();
 
-                if !self.github_service.note_exists(readme_path_str).await? {
+                if !self.store.exists(&format!("notes/{}", readme_id)).await? {
                     let dir_name = component.to_str().unwrap().replace('-', " ");
-                    let readme_content = if readme_path_str == format!("notes/{}", path) {
+                    let readme_content = if readme_id == id {
                         content.to_string()
                     } else {
                         format!("# {}", dir_name)
                     };
-                    let commit_message = format!("feat: create category README by {}", self.app_identifier);
-                    self.github_service
-                        .create_file(readme_path_str, &commit_message, &readme_content)
-                        .await?;
+                    batch.expect(readme_id, None).put(readme_id, readme_content);
                 }
             }
         }
         Ok(())
     }
 
-    pub async fn update_note(&self, id: &str, payload: &UpdateNote) -> Result<(), NoteServiceError> {
+    pub async fn update_note(&self, id: &str, payload: &UpdateNote) -> Result<SavedNote, NoteServiceError> {
         let path = format!("notes/{}", id);
-        let sha = self.github_service.get_sha(&path).await?;
+        let content = with_metadata(&payload.content, payload.metadata.as_ref());
+        let revision = self.store.revision(&path).await?;
+        if let Some(base_revision) = payload.revision.as_deref().filter(|base| *base != revision) {
+            let merged = self
+                .merge_concurrent_edit(&path, base_revision, revision.clone(), &content)
+                .await?;
+            let commit_message = format!("feat: merge concurrent note edits by {}", self.app_identifier);
+            let revision = self.store.update(&path, &commit_message, &merged, &revision).await?;
+            self.search_index.upsert(&path, &merged);
+            return Ok(SavedNote {
+                revision,
+                content: merged,
+            });
+        }
         let commit_message = format!("feat: update note by {}", self.app_identifier);
-        self.github_service
-            .update_file(&path, &commit_message, &payload.content, &sha)
+        let revision = self.store.update(&path, &commit_message, &content, &revision).await?;
+        self.search_index.upsert(&path, &content);
+        Ok(SavedNote { revision, content })
+    }
+
+    /// Merges `ours`, written against `base_revision`, with the note's current content. Returns
+    /// the merged content, or a `Conflict` when the edits overlap or the base is unavailable.
+    async fn merge_concurrent_edit(
+        &self,
+        path: &str,
+        base_revision: &str,
+        current_revision: String,
+        ours: &str,
+    ) -> Result<String, NoteServiceError> {
+        let theirs = self.store.read(path).await?.unwrap_or_default();
+        let Some(base) = self.store.read_revision(path, base_revision).await? else {
+            return Err(NoteServiceError::Conflict(NoteConflict {
+                current_revision,
+                current_content: theirs,
+                ..Default::default()
+            }));
+        };
+
+        let merge = merge::merge(&base, ours, &theirs);
+        if merge.is_clean() {
+            return Ok(merge.content);
+        }
+        Err(NoteServiceError::Conflict(NoteConflict {
+            current_revision,
+            current_content: theirs,
+            merged_content: Some(merge.content),
+            hunks: merge.conflicts,
+        }))
+    }
+
+    /// Moves or renames the note or category at `from` to `to` in one commit, creating the README
+    /// of every category above `to` that does not exist yet.
+    pub async fn move_note(&self, from: &str, to: &str) -> Result<(), NoteServiceError> {
+        let (from, to) = (from.trim_matches('/'), to.trim_matches('/'));
+        check_outside(from, to, "move")?;
+        let from_path = format!("notes/{}", from);
+        let files = list_files(&self.store, &from_path).await?;
+        if files.is_empty() {
+            return Err(NoteServiceError::NotFound);
+        }
+        if self.store.exists(&format!("notes/{}", to)).await? {
+            return Err(NoteServiceError::NoteAlreadyExists);
+        }
+
+        let mut batch = NoteBatch::new();
+        self.add_missing_parents(&mut batch, to, "").await?;
+        self.add_copies(&mut batch, &files, &from_path, to, false);
+        for file in &files {
+            let id = file.path.strip_prefix("notes/").unwrap_or(&file.path);
+            // Not moved over an edit made since the files were listed.
+            if let Some(revision) = &file.revision {
+                batch.expect(id, Some(revision.clone()));
+            }
+            batch.delete(id);
+        }
+
+        let commit_message = format!("feat: move note by {}", self.app_identifier);
+        self.commit_batch(&commit_message, batch).await
+    }
+
+    /// Copies the note or category at `from` to `to` in one commit, creating the README of every
+    /// category above `to` that does not exist yet. Fails with `NoteAlreadyExists` if something
+    /// exists at `to`, unless `overwrite` is set; then copied notes replace the notes at the same
+    /// paths and other notes below `to` are kept.
+    pub async fn copy_note(&self, from: &str, to: &str, overwrite: bool) -> Result<(), NoteServiceError> {
+        let (from, to) = (from.trim_matches('/'), to.trim_matches('/'));
+        check_outside(from, to, "copy")?;
+        let from_path = format!("notes/{}", from);
+        let files = list_files(&self.store, &from_path).await?;
+        if files.is_empty() {
+            return Err(NoteServiceError::NotFound);
+        }
+        if !overwrite && self.store.exists(&format!("notes/{}", to)).await? {
+            return Err(NoteServiceError::NoteAlreadyExists);
+        }
+
+        let mut batch = NoteBatch::new();
+        self.add_missing_parents(&mut batch, to, "").await?;
+        self.add_copies(&mut batch, &files, &from_path, to, overwrite);
+
+        let commit_message = format!("feat: copy note by {}", self.app_identifier);
+        self.commit_batch(&commit_message, batch).await
+    }
+
+    /// Stores `content` as the attachment `name` in the `assets/` folder of `category`, creating
+    /// any missing categories like `create_note` does. An existing attachment with the same name
+    /// is only replaced if `overwrite` is set.
+    pub async fn add_attachment(
+        &self,
+        category: &str,
+        name: &str,
+        content: Vec<u8>,
+        overwrite: bool,
+    ) -> Result<Attachment, NoteServiceError> {
+        let mut attachments = self
+            .add_attachments(category, vec![(name.to_string(), content)], overwrite)
             .await?;
-        Ok(())
+        Ok(attachments.remove(0))
+    }
+
+    /// Stores each of `files`, given as name and content, like `add_attachment` does, all in one
+    /// commit. If any of them cannot be stored, none are.
+    pub async fn add_attachments(
+        &self,
+        category: &str,
+        files: Vec<(String, Vec<u8>)>,
+        overwrite: bool,
+    ) -> Result<Vec<Attachment>, NoteServiceError> {
+        let mut names = HashSet::new();
+        for (name, _) in &files {
+            if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+                return Err(NoteServiceError::InvalidAttachment(format!(
+                    "invalid file name '{}'",
+                    name
+                )));
+            }
+            if !names.insert(name.as_str()) {
+                return Err(NoteServiceError::InvalidAttachment(format!(
+                    "file name '{}' is given more than once",
+                    name
+                )));
+            }
+        }
+        let dir = match category.trim_matches('/') {
+            "" => ATTACHMENTS_DIR.to_string(),
+            category => format!("{}/{}", category, ATTACHMENTS_DIR),
+        };
+
+        let mut batch = NoteBatch::new();
+        // Only the categories above the attachments folder get a README.
+        self.add_missing_parents(&mut batch, &dir, "").await?;
+        let mut attachments = Vec::new();
+        for (name, content) in files {
+            let id = format!("{}/{}", dir, name);
+            if !overwrite {
+                batch.expect(&id, None);
+            }
+            attachments.push(Attachment {
+                id: format!("notes/{}", id),
+                link: format!("{}/{}", ATTACHMENTS_DIR, name),
+                name,
+                size: content.len(),
+            });
+            batch.put_bytes(&id, content);
+        }
+        let commit_message = format!("feat: add attachment by {}", self.app_identifier);
+        self.commit_batch(&commit_message, batch).await?;
+        Ok(attachments)
+    }
+
+    /// Reads the file at `id` as raw bytes, e.g. an attachment.
+    pub async fn get_attachment(&self, id: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        self.store.read_bytes(&format!("notes/{}", id.trim_matches('/'))).await
+    }
+
+    /// Adds a copy of each of `files`, found below `from_path`, to `batch` at the same place below
+    /// `to`. The files are copied by the revision they were listed with, so they are not read here.
+    /// Unless `overwrite` is set, each copy expects nothing to be at its path yet.
+    fn add_copies(&self, batch: &mut NoteBatch, files: &[StoreEntry], from_path: &str, to: &str, overwrite: bool) {
+        for file in files {
+            let suffix = file.path.strip_prefix(from_path).unwrap_or_default();
+            let from = file.path.strip_prefix("notes/").unwrap_or(&file.path);
+            let to = format!("{}{}", to, suffix);
+            if !overwrite {
+                batch.expect(&to, None);
+            }
+            batch.copy(from, &to, file.revision.clone());
+        }
     }
 
     pub async fn delete_note(&self, id: &str) -> Result<(), NoteServiceError> {
         let path = format!("notes/{}", id);
-        let sha = self.github_service.get_sha(&path).await?;
+        let revision = self.store.revision(&path).await?;
         let commit_message = format!("feat: delete note by {}", self.app_identifier);
-        self.github_service.delete_file(&path, &commit_message, &sha).await?;
+        self.store.delete(&path, &commit_message, &revision).await?;
+        self.search_index.remove(&path);
         Ok(())
     }
+
+    /// Deletes the note or category at `id` and everything below it in a single commit, where the
+    /// store has commits. Other stores delete the files one at a time and may stop part way, so
+    /// when the deletion fails each file is checked and reported as it was left.
+    pub async fn delete_tree(&self, id: &str) -> Result<DeleteReport, NoteServiceError> {
+        let path = format!("notes/{}", id);
+        let files = list_files(&self.store, &path).await?;
+        if files.is_empty() {
+            return Err(NoteServiceError::NotFound);
+        }
+
+        let mut batch = NoteBatch::new();
+        for file in &files {
+            // Not deleted over an edit made since the files were listed.
+            if let Some(revision) = &file.revision {
+                batch.changes.push(StoreChange::Expect {
+                    path: file.path.clone(),
+                    revision: Some(revision.clone()),
+                });
+            }
+            batch.changes.push(StoreChange::Delete {
+                path: file.path.clone(),
+            });
+        }
+        let commit_message = format!("feat: delete note by {}", self.app_identifier);
+        let error = self
+            .commit_batch(&commit_message, batch)
+            .await
+            .err()
+            .map(|e| e.to_string());
+
+        let mut report = DeleteReport::default();
+        for file in files {
+            let error = match &error {
+                None => None,
+                Some(error) => match self.store.exists(&file.path).await {
+                    Ok(false) => {
+                        self.search_index.remove(&file.path);
+                        None
+                    }
+                    _ => Some(error.clone()),
+                },
+            };
+            match error {
+                None => report.deleted.push(file.path),
+                Some(error) => report.failed.push(DeleteFailure { path: file.path, error }),
+            }
+        }
+        Ok(report)
+    }
 }
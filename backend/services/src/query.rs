@@ -0,0 +1,555 @@
+use chrono::NaiveDate;
+
+use crate::note_service::NoteServiceError;
+use crate::tags;
+
+/// The most tokens a query may have. Queries arrive in URLs, so this only stops abuse.
+const MAX_TOKENS: usize = 256;
+/// How deeply `NOT` and parentheses may nest. Parsing, matching and dropping a query recurse
+/// once per level, so deeper queries could overflow the stack.
+const MAX_DEPTH: usize = 32;
+
+/// How a `modified:` filter compares a note's last modification date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Before,
+    AtOrBefore,
+    On,
+    AtOrAfter,
+    After,
+}
+
+impl Comparison {
+    fn holds(self, date: NaiveDate, bound: NaiveDate) -> bool {
+        match self {
+            Comparison::Before => date < bound,
+            Comparison::AtOrBefore => date <= bound,
+            Comparison::On => date == bound,
+            Comparison::AtOrAfter => date >= bound,
+            Comparison::After => date > bound,
+        }
+    }
+}
+
+/// A parsed search query.
+///
+/// Words are matched case-insensitively against note contents, and every word must match unless
+/// `OR` is used. The syntax also supports:
+///
+/// - `"exact phrase"`
+/// - `path:work/` – notes whose path below `notes/` starts with `work/`
+/// - `tag:foo` – notes tagged `#foo`, or with a tag nested below it like `#foo/bar`
+/// - `modified:>2026-01-01` – also `>=`, `<`, `<=` or a plain date for that day
+/// - `AND`, `OR`, `NOT` (or a leading `-`) and parentheses, with `NOT` binding tightest and
+///   `OR` loosest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// Matches every note. This is what an empty query parses to.
+    All,
+    Term(String),
+    /// Consecutive lowercase words.
+    Phrase(Vec<String>),
+    Path(String),
+    Tag(String),
+    Modified(Comparison, NaiveDate),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+/// A note a query is matched against.
+pub trait Target {
+    /// The note's path below `notes/`.
+    fn path(&self) -> &str;
+    fn has_term(&self, term: &str) -> bool;
+    fn has_phrase(&self, words: &[String]) -> bool;
+    fn has_tag(&self, tag: &str) -> bool;
+    /// The date the note was last modified, `Some(None)` if it is unknown, or `None` if it has
+    /// not been looked up yet.
+    fn modified(&self) -> Option<Option<NaiveDate>>;
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, NoteServiceError> {
+        let tokens = lex(input)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            depth: 0,
+        };
+        let query = parser.or()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some(token) => Err(invalid(format!("unexpected {}", token.describe()))),
+        }
+    }
+
+    /// Whether `target` matches. `None` means the answer depends on `target.modified()`, which
+    /// has not been looked up yet.
+    pub fn matches(&self, target: &impl Target) -> Option<bool> {
+        match self {
+            Query::All => Some(true),
+            Query::Term(term) => Some(target.has_term(term)),
+            Query::Phrase(words) => Some(target.has_phrase(words)),
+            Query::Path(prefix) => Some(target.path().starts_with(prefix.as_str())),
+            Query::Tag(tag) => Some(target.has_tag(tag)),
+            Query::Modified(comparison, bound) => target
+                .modified()
+                .map(|date| date.is_some_and(|date| comparison.holds(date, *bound))),
+            Query::And(queries) => {
+                let mut result = Some(true);
+                for query in queries {
+                    match query.matches(target) {
+                        Some(false) => return Some(false),
+                        None => result = None,
+                        Some(true) => {}
+                    }
+                }
+                result
+            }
+            Query::Or(queries) => {
+                let mut result = Some(false);
+                for query in queries {
+                    match query.matches(target) {
+                        Some(true) => return Some(true),
+                        None => result = None,
+                        Some(false) => {}
+                    }
+                }
+                result
+            }
+            Query::Not(query) => query.matches(target).map(|matched| !matched),
+        }
+    }
+
+    /// The words a matching note is expected to contain, for ranking and snippets. Words under
+    /// `NOT` are left out.
+    pub fn positive_terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.collect_terms(&mut terms);
+        terms.sort();
+        terms.dedup();
+        terms
+    }
+
+    fn collect_terms(&self, terms: &mut Vec<String>) {
+        match self {
+            Query::Term(term) => terms.push(term.clone()),
+            Query::Phrase(words) => terms.extend(words.iter().cloned()),
+            Query::And(queries) | Query::Or(queries) => {
+                for query in queries {
+                    query.collect_terms(terms);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn invalid(message: String) -> NoteServiceError {
+    NoteServiceError::InvalidQuery(message)
+}
+
+/// Splits `text` into lowercase words, the unit of matching.
+pub(crate) fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Field(String, String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("'{}'", word),
+            Token::Phrase(phrase) => format!("\"{}\"", phrase),
+            Token::Field(field, value) => format!("'{}:{}'", field, value),
+            Token::And => "AND".to_string(),
+            Token::Or => "OR".to_string(),
+            Token::Not => "NOT".to_string(),
+            Token::Open => "'('".to_string(),
+            Token::Close => "')'".to_string(),
+        }
+    }
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, NoteServiceError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if tokens.len() == MAX_TOKENS && !c.is_whitespace() {
+            return Err(invalid(format!(
+                "query has more than {} terms and operators",
+                MAX_TOKENS
+            )));
+        }
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Phrase(quoted(&mut chars)?));
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' && word.ends_with(':') {
+                        // A quoted field value, e.g. `path:"work notes/"`.
+                        word.push_str(&quoted(&mut chars)?);
+                        break;
+                    }
+                    word.push(c);
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match word.split_once(':') {
+                        Some((field, value)) if matches!(field, "path" | "tag" | "modified") => {
+                            Token::Field(field.to_string(), value.to_string())
+                        }
+                        _ => Token::Word(word),
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads up to the closing quote, after the opening one.
+fn quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, NoteServiceError> {
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            return Ok(text);
+        }
+        text.push(c);
+    }
+    Err(invalid("unterminated quote".to_string()))
+}
+
+/// Recursive descent over `or := and (OR and)*`, `and := unary (AND? unary)*` and
+/// `unary := NOT unary | '(' or ')' | term`.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// How many `NOT`s and parentheses enclose the current position.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Runs `parse` one level deeper, failing past `MAX_DEPTH`.
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Query, NoteServiceError>,
+    ) -> Result<Query, NoteServiceError> {
+        if self.depth == MAX_DEPTH {
+            return Err(invalid(format!(
+                "query nests NOT or parentheses more than {} deep",
+                MAX_DEPTH
+            )));
+        }
+        self.depth += 1;
+        let query = parse(self);
+        self.depth -= 1;
+        query
+    }
+
+    fn or(&mut self) -> Result<Query, NoteServiceError> {
+        let mut queries = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            queries.push(self.and()?);
+        }
+        if queries.len() > 1 && queries.iter().any(Option::is_none) {
+            return Err(invalid("OR must be between two terms".to_string()));
+        }
+        Ok(combine(queries.into_iter().flatten().collect(), Query::Or))
+    }
+
+    /// Returns `None` when there is nothing to combine.
+    fn and(&mut self) -> Result<Option<Query>, NoteServiceError> {
+        let mut queries = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) => {
+                    self.next();
+                }
+                Some(_) => queries.push(self.unary()?),
+            }
+        }
+        Ok((!queries.is_empty()).then(|| combine(queries, Query::And)))
+    }
+
+    fn unary(&mut self) -> Result<Query, NoteServiceError> {
+        match self.next() {
+            Some(Token::Not) => match self.peek() {
+                None | Some(Token::Or) | Some(Token::And) | Some(Token::Close) => {
+                    Err(invalid("NOT must be followed by a term".to_string()))
+                }
+                _ => self.nested(|parser| Ok(Query::Not(Box::new(parser.unary()?)))),
+            },
+            Some(Token::Open) => {
+                let query = self.nested(Parser::or)?;
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err(invalid("missing ')'".to_string())),
+                }
+            }
+            Some(Token::Word(word)) => Ok(text_query(&word)),
+            Some(Token::Phrase(phrase)) => Ok(text_query(&phrase)),
+            Some(Token::Field(field, value)) => field_query(&field, &value),
+            Some(token) => Err(invalid(format!("unexpected {}", token.describe()))),
+            None => Err(invalid("unexpected end of query".to_string())),
+        }
+    }
+}
+
+fn combine(mut queries: Vec<Query>, group: fn(Vec<Query>) -> Query) -> Query {
+    match queries.len() {
+        0 => Query::All,
+        1 => queries.remove(0),
+        _ => group(queries),
+    }
+}
+
+/// A word or phrase, split into words the way note contents are.
+fn text_query(text: &str) -> Query {
+    let mut words: Vec<String> = words(text).collect();
+    match words.len() {
+        0 => Query::All,
+        1 => Query::Term(words.remove(0)),
+        _ => Query::Phrase(words),
+    }
+}
+
+fn field_query(field: &str, value: &str) -> Result<Query, NoteServiceError> {
+    if value.is_empty() {
+        return Err(invalid(format!("{}: needs a value", field)));
+    }
+    match field {
+        "path" => Ok(Query::Path(value.trim_start_matches('/').to_string())),
+        "tag" => tags::normalize(value)
+            .map(Query::Tag)
+            .ok_or_else(|| invalid(format!("invalid tag '{}'", value))),
+        _ => {
+            let (comparison, date) = if let Some(date) = value.strip_prefix(">=") {
+                (Comparison::AtOrAfter, date)
+            } else if let Some(date) = value.strip_prefix("<=") {
+                (Comparison::AtOrBefore, date)
+            } else if let Some(date) = value.strip_prefix('>') {
+                (Comparison::After, date)
+            } else if let Some(date) = value.strip_prefix('<') {
+                (Comparison::Before, date)
+            } else {
+                (Comparison::On, value.strip_prefix('=').unwrap_or(value))
+            };
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| invalid(format!("invalid date '{}', expected YYYY-MM-DD", date)))?;
+            Ok(Query::Modified(comparison, date))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Note {
+        path: &'static str,
+        words: Vec<String>,
+        tags: Vec<&'static str>,
+        modified: Option<Option<NaiveDate>>,
+    }
+
+    impl Note {
+        fn new(path: &'static str, content: &str) -> Self {
+            Self {
+                path,
+                words: words(content).collect(),
+                tags: Vec::new(),
+                modified: Some(None),
+            }
+        }
+    }
+
+    impl Target for Note {
+        fn path(&self) -> &str {
+            self.path
+        }
+
+        fn has_term(&self, term: &str) -> bool {
+            self.words.iter().any(|word| word == term)
+        }
+
+        fn has_phrase(&self, words: &[String]) -> bool {
+            self.words.windows(words.len()).any(|window| window == words)
+        }
+
+        fn has_tag(&self, tag: &str) -> bool {
+            self.tags.iter().any(|own| tags::is_within(own, tag))
+        }
+
+        fn modified(&self) -> Option<Option<NaiveDate>> {
+            self.modified
+        }
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    fn term(word: &str) -> Query {
+        Query::Term(word.to_string())
+    }
+
+    #[test]
+    fn parses_terms_and_fields() {
+        assert_eq!(Query::parse("").unwrap(), Query::All);
+        assert_eq!(
+            Query::parse("Rust \"async  Traits\" path:/work/ tag:#Lang modified:>=2026-01-02").unwrap(),
+            Query::And(vec![
+                term("rust"),
+                Query::Phrase(vec!["async".to_string(), "traits".to_string()]),
+                Query::Path("work/".to_string()),
+                Query::Tag("lang".to_string()),
+                Query::Modified(Comparison::AtOrAfter, date("2026-01-02")),
+            ])
+        );
+        assert_eq!(
+            Query::parse("path:\"work notes/\"").unwrap(),
+            Query::Path("work notes/".to_string())
+        );
+    }
+
+    #[test]
+    fn binds_not_tightest_and_or_loosest() {
+        assert_eq!(
+            Query::parse("a b OR -c AND NOT (d OR e)").unwrap(),
+            Query::Or(vec![
+                Query::And(vec![term("a"), term("b")]),
+                Query::And(vec![
+                    Query::Not(Box::new(term("c"))),
+                    Query::Not(Box::new(Query::Or(vec![term("d"), term("e")]))),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        for input in [
+            "(a",
+            "a)",
+            "\"open",
+            "OR a",
+            "a NOT",
+            "tag:",
+            "modified:yesterday",
+            "tag:#",
+        ] {
+            assert!(
+                matches!(Query::parse(input), Err(NoteServiceError::InvalidQuery(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_deeply_nested_and_long_queries() {
+        for input in [
+            format!("{}a", "-".repeat(60_000)),
+            format!("{}a{}", "(".repeat(10_000), ")".repeat(10_000)),
+            format!("{}a", "NOT ".repeat(MAX_DEPTH + 1)),
+            "a ".repeat(MAX_TOKENS + 1),
+        ] {
+            assert!(matches!(Query::parse(&input), Err(NoteServiceError::InvalidQuery(_))));
+        }
+        let nested = format!("{}a{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert_eq!(Query::parse(&nested).unwrap(), term("a"));
+    }
+
+    #[test]
+    fn matches_notes() {
+        let mut note = Note::new("work/plans.md", "Ship the async runtime");
+        note.tags = vec!["project/alpha"];
+        let matches = |input: &str| Query::parse(input).unwrap().matches(&note);
+
+        assert_eq!(matches("ship runtime"), Some(true));
+        assert_eq!(matches("ship cake"), Some(false));
+        assert_eq!(matches("cake OR \"async runtime\""), Some(true));
+        assert_eq!(matches("\"runtime async\""), Some(false));
+        assert_eq!(matches("path:work/ tag:project -tag:project/beta"), Some(true));
+        assert_eq!(matches("path:home/"), Some(false));
+    }
+
+    #[test]
+    fn defers_to_the_modification_date() {
+        let mut note = Note::new("a.md", "text");
+        note.modified = None;
+        let query = Query::parse("text modified:<2026-02-01").unwrap();
+        assert_eq!(query.matches(&note), None);
+        assert_eq!(
+            Query::parse("cake modified:<2026-02-01").unwrap().matches(&note),
+            Some(false)
+        );
+        assert_eq!(
+            Query::parse("text OR modified:<2026-02-01").unwrap().matches(&note),
+            Some(true)
+        );
+
+        note.modified = Some(Some(date("2026-01-31")));
+        assert_eq!(query.matches(&note), Some(true));
+        note.modified = Some(Some(date("2026-02-01")));
+        assert_eq!(query.matches(&note), Some(false));
+        note.modified = Some(None);
+        assert_eq!(query.matches(&note), Some(false));
+    }
+
+    #[test]
+    fn leaves_negated_words_out_of_positive_terms() {
+        let query = Query::parse("beta alpha -gamma \"alpha delta\"").unwrap();
+        assert_eq!(query.positive_terms(), ["alpha", "beta", "delta"]);
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::links::{self, Backlink, BrokenLink, BrokenLinks, Link, LinkContext, Resolver};
+use crate::query::{words, Query, Target};
+use crate::tags::{is_within, note_tags, with_parents, TagCount, TaggedNote};
+
+/// Hits returned by a search, best first.
+pub const MAX_RESULTS: usize = 50;
+const MAX_MATCHES_PER_HIT: usize = 3;
+const SNIPPET_CHARS: usize = 160;
+/// Characters kept before the first matching term when a snippet has to be cut.
+const SNIPPET_LEAD: usize = 40;
+
+// BM25 parameters.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// Added to the score for each query term that appears in the note's path.
+const PATH_BOOST: f64 = 1.0;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchMatch {
+    /// 1-based line number of the snippet in the note.
+    pub line: usize,
+    pub snippet: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchHit {
+    pub id: String,
+    pub name: String,
+    pub score: f64,
+    pub matches: Vec<SearchMatch>,
+}
+
+/// A note as stored in the index.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedNote {
+    pub path: String,
+    /// The revision `content` was read at, or `None` if unknown.
+    pub revision: Option<String>,
+    pub content: String,
+}
+
+/// What a persistent index saves to disk. Term counts are rebuilt from the content on load.
+#[derive(Serialize, Deserialize, Default)]
+struct Snapshot {
+    commit: Option<String>,
+    notes: Vec<IndexedNote>,
+}
+
+struct Document {
+    revision: Option<String>,
+    content: String,
+    term_counts: HashMap<String, usize>,
+    path_terms: HashSet<String>,
+    tags: HashSet<String>,
+    links: Vec<Link>,
+    length: usize,
+    /// The last modification date of the note at `revision`, once it has been looked up.
+    modified: Option<Option<NaiveDate>>,
+}
+
+impl Document {
+    fn new(path: &str, revision: Option<String>, content: String) -> Self {
+        let mut term_counts = HashMap::new();
+        let mut length = 0;
+        for term in words(&content) {
+            *term_counts.entry(term).or_insert(0) += 1;
+            length += 1;
+        }
+        Self {
+            revision,
+            tags: note_tags(&content),
+            links: links::extract(&content),
+            content,
+            term_counts,
+            path_terms: words(path).collect(),
+            length,
+            modified: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct IndexState {
+    /// The commit the indexed notes are at, if they match one exactly.
+    commit: Option<String>,
+    documents: HashMap<String, Document>,
+    /// The paths of the documents containing each term.
+    postings: HashMap<String, HashSet<String>>,
+    total_length: usize,
+}
+
+impl IndexState {
+    fn insert(&mut self, path: &str, revision: Option<String>, content: String) {
+        self.remove(path);
+        let document = Document::new(path, revision, content);
+        for term in document.term_counts.keys() {
+            self.postings.entry(term.clone()).or_default().insert(path.to_string());
+        }
+        self.total_length += document.length;
+        self.documents.insert(path.to_string(), document);
+    }
+
+    fn remove(&mut self, path: &str) {
+        let Some(document) = self.documents.remove(path) else {
+            return;
+        };
+        for term in document.term_counts.keys() {
+            if let Some(paths) = self.postings.get_mut(term) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.total_length -= document.length;
+    }
+}
+
+/// In-memory full-text index of note contents.
+///
+/// `NoteService` fills the index from the store on the first search, keeps it up to date as it
+/// writes notes, and checks it against the store's head commit before every query. A persistent
+/// index is also saved to disk each time it is filled, so a later session only has to re-read the
+/// notes that changed since.
+#[derive(Default)]
+pub struct SearchIndex {
+    state: RwLock<IndexState>,
+    file: Option<PathBuf>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An index saved to `file`, starting from the notes saved there by an earlier session. A
+    /// missing or unreadable file starts an empty index.
+    pub fn persistent(file: impl Into<PathBuf>) -> Self {
+        let file = file.into();
+        let snapshot: Snapshot = std::fs::read(&file)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        let mut state = IndexState {
+            commit: snapshot.commit,
+            ..Default::default()
+        };
+        for note in snapshot.notes {
+            state.insert(&note.path, note.revision, note.content);
+        }
+        Self {
+            state: RwLock::new(state),
+            file: Some(file),
+        }
+    }
+
+    /// The commit the indexed notes are at, if they match one exactly.
+    pub fn commit(&self) -> Option<String> {
+        self.state.read().unwrap().commit.clone()
+    }
+
+    /// The indexed content of the note at `path`, if it is still at `revision`.
+    pub fn content_at(&self, path: &str, revision: &str) -> Option<String> {
+        let state = self.state.read().unwrap();
+        let document = state.documents.get(path)?;
+        (document.revision.as_deref() == Some(revision)).then(|| document.content.clone())
+    }
+
+    /// Replaces the indexed notes with `notes`, read at `commit`, and saves them if the index is
+    /// persistent.
+    pub fn load(&self, commit: Option<String>, notes: Vec<IndexedNote>) {
+        let mut state = IndexState {
+            commit,
+            ..Default::default()
+        };
+        for note in notes {
+            state.insert(&note.path, note.revision, note.content);
+        }
+        let mut current = self.state.write().unwrap();
+        // Notes still at the same revision keep the dates already looked up.
+        for (path, document) in &mut state.documents {
+            if let Some(previous) = current.documents.get(path) {
+                if document.revision.is_some() && previous.revision == document.revision {
+                    document.modified = previous.modified;
+                }
+            }
+        }
+        *current = state;
+        drop(current);
+        self.save();
+    }
+
+    /// Forgets the commit the index was filled at, e.g. after the notes changed outside of
+    /// `NoteService`. The next search checks the revision of every note again, but only re-reads
+    /// those whose revision changed.
+    pub fn invalidate(&self) {
+        self.state.write().unwrap().commit = None;
+    }
+
+    /// Indexes the new content of the note at `path`.
+    pub fn upsert(&self, path: &str, content: &str) {
+        let mut state = self.state.write().unwrap();
+        state.insert(path, None, content.to_string());
+        state.commit = None;
+    }
+
+    /// Indexes the note at `path` with the indexed content of `from`, which it is a copy of.
+    pub fn copy(&self, from: &str, path: &str) {
+        let mut state = self.state.write().unwrap();
+        if let Some(document) = state.documents.get(from) {
+            let (revision, content) = (document.revision.clone(), document.content.clone());
+            state.insert(path, revision, content);
+        }
+        state.commit = None;
+    }
+
+    pub fn remove(&self, path: &str) {
+        let mut state = self.state.write().unwrap();
+        state.remove(path);
+        state.commit = None;
+    }
+
+    /// Writes the indexed notes to the index file. Saving is best effort: an index that fails to
+    /// save is simply rebuilt by the next session.
+    fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let json = {
+            let state = self.state.read().unwrap();
+            let snapshot = Snapshot {
+                commit: state.commit.clone(),
+                notes: state
+                    .documents
+                    .iter()
+                    .map(|(path, document)| IndexedNote {
+                        path: path.clone(),
+                        revision: document.revision.clone(),
+                        content: document.content.clone(),
+                    })
+                    .collect(),
+            };
+            serde_json::to_vec(&snapshot)
+        };
+        if let (Ok(json), Some(dir)) = (json, file.parent()) {
+            let _ = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(file, json));
+        }
+    }
+
+    /// Whether the note at `path` matches `query`, given its last modification date if it has
+    /// been looked up, now or for an earlier search. See `Query::matches`.
+    pub fn matches(&self, query: &Query, path: &str, modified: Option<Option<NaiveDate>>) -> Option<bool> {
+        let state = self.state.read().unwrap();
+        let document = state.documents.get(path);
+        let target = DocumentTarget {
+            path: path.strip_prefix("notes/").unwrap_or(path),
+            document,
+            modified: modified.or_else(|| document.and_then(|document| document.modified)),
+        };
+        query.matches(&target)
+    }
+
+    /// Remembers the last modification date of the note at `path`, for as long as it stays at the
+    /// indexed revision. Notes written since the index was loaded have no revision to tie it to.
+    pub fn set_modified(&self, path: &str, modified: Option<NaiveDate>) {
+        let mut state = self.state.write().unwrap();
+        if let Some(document) = state.documents.get_mut(path) {
+            if document.revision.is_some() {
+                document.modified = Some(modified);
+            }
+        }
+    }
+
+    /// Every tag used in the indexed notes, including the tags others are nested below, sorted
+    /// by name.
+    pub fn tags(&self) -> Vec<TagCount> {
+        let state = self.state.read().unwrap();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for document in state.documents.values() {
+            let tags: HashSet<&str> = document.tags.iter().flat_map(|tag| with_parents(tag)).collect();
+            for tag in tags {
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+        }
+        let mut tags: Vec<TagCount> = counts
+            .into_iter()
+            .map(|(tag, count)| TagCount {
+                tag: tag.to_string(),
+                count,
+            })
+            .collect();
+        tags.sort_by(|a, b| a.tag.cmp(&b.tag));
+        tags
+    }
+
+    /// The indexed notes tagged with `tag` or a tag nested below it, sorted by path.
+    pub fn tagged(&self, tag: &str) -> Vec<TaggedNote> {
+        let state = self.state.read().unwrap();
+        let mut notes: Vec<TaggedNote> = state
+            .documents
+            .iter()
+            .filter_map(|(path, document)| {
+                let mut tags: Vec<String> = document
+                    .tags
+                    .iter()
+                    .filter(|own| is_within(own, tag))
+                    .cloned()
+                    .collect();
+                if tags.is_empty() {
+                    return None;
+                }
+                tags.sort();
+                Some(TaggedNote {
+                    id: path.clone(),
+                    name: path.rsplit('/').next().unwrap_or_default().to_string(),
+                    tags,
+                })
+            })
+            .collect();
+        notes.sort_by(|a, b| a.id.cmp(&b.id));
+        notes
+    }
+
+    /// The indexed notes with links that resolve to the note at `path`, sorted by path, with each
+    /// line that links to it once.
+    pub fn backlinks(&self, path: &str) -> Vec<Backlink> {
+        let state = self.state.read().unwrap();
+        let resolver = Resolver::new(state.documents.keys().cloned());
+        let mut backlinks: Vec<Backlink> = state
+            .documents
+            .iter()
+            .filter(|(source, _)| source.as_str() != path)
+            .filter_map(|(source, document)| {
+                let lines: Vec<&str> = document.content.lines().collect();
+                let mut links: Vec<LinkContext> = document
+                    .links
+                    .iter()
+                    .filter(|link| resolver.resolve(source, link).as_deref() == Some(path))
+                    .map(|link| LinkContext {
+                        line: link.line,
+                        context: snippet(lines[link.line - 1], link.offset),
+                    })
+                    .collect();
+                links.dedup_by_key(|link| link.line);
+                (!links.is_empty()).then(|| Backlink {
+                    id: source.clone(),
+                    name: source.rsplit('/').next().unwrap_or_default().to_string(),
+                    links,
+                })
+            })
+            .collect();
+        backlinks.sort_by(|a, b| a.id.cmp(&b.id));
+        backlinks
+    }
+
+    /// The links in the indexed notes that resolve to no indexed file, grouped by the note they
+    /// are written in and sorted by path.
+    pub fn broken_links(&self) -> Vec<BrokenLinks> {
+        let state = self.state.read().unwrap();
+        let resolver = Resolver::new(state.documents.keys().cloned());
+        let mut broken: Vec<BrokenLinks> = state
+            .documents
+            .iter()
+            .filter_map(|(source, document)| {
+                let lines: Vec<&str> = document.content.lines().collect();
+                let links: Vec<BrokenLink> = document
+                    .links
+                    .iter()
+                    .filter(|link| resolver.resolve(source, link).is_none())
+                    .map(|link| BrokenLink {
+                        kind: link.kind,
+                        target: link.target.clone(),
+                        line: link.line,
+                        context: snippet(lines[link.line - 1], link.offset),
+                    })
+                    .collect();
+                (!links.is_empty()).then(|| BrokenLinks {
+                    id: source.clone(),
+                    name: source.rsplit('/').next().unwrap_or_default().to_string(),
+                    links,
+                })
+            })
+            .collect();
+        broken.sort_by(|a, b| a.id.cmp(&b.id));
+        broken
+    }
+
+    /// Whether a note at `path` is indexed.
+    pub fn contains(&self, path: &str) -> bool {
+        self.state.read().unwrap().documents.contains_key(path)
+    }
+
+    /// Ranks the notes at `paths`, which match `query`, with BM25 over the query's words, boosted
+    /// when the words also appear in the note's path.
+    pub fn rank(&self, query: &Query, paths: Vec<String>, limit: usize) -> Vec<SearchHit> {
+        let terms = query.positive_terms();
+        let state = self.state.read().unwrap();
+        let document_count = state.documents.len() as f64;
+        let average_length = (state.total_length as f64 / document_count.max(1.0)).max(1.0);
+
+        let mut hits: Vec<SearchHit> = paths
+            .into_iter()
+            .map(|path| {
+                let Some(document) = state.documents.get(&path) else {
+                    return SearchHit {
+                        name: path.rsplit('/').next().unwrap_or_default().to_string(),
+                        id: path,
+                        score: 0.0,
+                        matches: Vec::new(),
+                    };
+                };
+                let score: f64 = terms
+                    .iter()
+                    .map(|term| {
+                        let frequency = document.term_counts.get(term).copied().unwrap_or_default() as f64;
+                        let containing = state.postings.get(term).map_or(0, HashSet::len) as f64;
+                        let idf = ((document_count - containing + 0.5) / (containing + 0.5) + 1.0).ln();
+                        let norm = K1 * (1.0 - B + B * document.length as f64 / average_length);
+                        let path_boost = if document.path_terms.contains(term) {
+                            PATH_BOOST
+                        } else {
+                            0.0
+                        };
+                        idf * frequency * (K1 + 1.0) / (frequency + norm) + path_boost
+                    })
+                    .sum();
+                SearchHit {
+                    name: path.rsplit('/').next().unwrap_or_default().to_string(),
+                    score,
+                    matches: matches(&document.content, &terms),
+                    id: path,
+                }
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+struct DocumentTarget<'a> {
+    path: &'a str,
+    /// `None` for a note the index has not seen, which is matched as empty.
+    document: Option<&'a Document>,
+    modified: Option<Option<NaiveDate>>,
+}
+
+impl Target for DocumentTarget<'_> {
+    fn path(&self) -> &str {
+        self.path
+    }
+
+    fn has_term(&self, term: &str) -> bool {
+        self.document
+            .is_some_and(|document| document.term_counts.contains_key(term))
+    }
+
+    fn has_phrase(&self, phrase: &[String]) -> bool {
+        let Some(document) = self.document else {
+            return false;
+        };
+        if !phrase.iter().all(|word| document.term_counts.contains_key(word)) {
+            return false;
+        }
+        let content: Vec<String> = words(&document.content).collect();
+        content.windows(phrase.len()).any(|window| window == phrase)
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.document
+            .is_some_and(|document| document.tags.iter().any(|own| is_within(own, tag)))
+    }
+
+    fn modified(&self) -> Option<Option<NaiveDate>> {
+        self.modified
+    }
+}
+
+/// The first lines containing any of `terms`, as snippets.
+fn matches(content: &str, terms: &[String]) -> Vec<SearchMatch> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let first_word = line
+                .split(|c: char| !c.is_alphanumeric())
+                .find(|word| terms.iter().any(|term| word.to_lowercase() == *term))?;
+            // `first_word` is a subslice of `line`, so the offset is its byte position.
+            let offset = first_word.as_ptr() as usize - line.as_ptr() as usize;
+            Some(SearchMatch {
+                line: index + 1,
+                snippet: snippet(line, offset),
+            })
+        })
+        .take(MAX_MATCHES_PER_HIT)
+        .collect()
+}
+
+/// Trims `line` to at most `SNIPPET_CHARS` characters around the byte `offset` of a match.
+fn snippet(line: &str, offset: usize) -> String {
+    let line_chars = line.chars().count();
+    if line_chars <= SNIPPET_CHARS {
+        return line.trim().to_string();
+    }
+    let match_char = line[..offset].chars().count();
+    let start = match_char.saturating_sub(SNIPPET_LEAD).min(line_chars - SNIPPET_CHARS);
+    let text: String = line.chars().skip(start).take(SNIPPET_CHARS).collect();
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if start + SNIPPET_CHARS < line_chars { "…" } else { "" };
+    format!("{}{}{}", prefix, text.trim(), suffix)
+}
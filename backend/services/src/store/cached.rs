@@ -0,0 +1,871 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use super::local::content_revision;
+use super::{check_expected, list_files, LocalStore, NoteStore, StoreChange, StoreEntry};
+use crate::merge;
+use crate::note_service::{NoteCommit, NoteConflict, NoteServiceError};
+
+const NOTES_ROOT: &str = "notes/";
+/// How long the content of a note read through `read` is kept, so that an edit made against it
+/// can still be merged with later changes.
+const BASE_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncState {
+    Idle,
+    Syncing,
+    Offline,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncStatus {
+    pub state: SyncState,
+    pub pending_changes: usize,
+    /// Unix timestamp (seconds) of the last successful sync.
+    pub last_synced: Option<u64>,
+    pub last_error: Option<String>,
+    /// Local changes the remote rejected, which were set aside so the rest of the queue could be
+    /// pushed.
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// A local change the remote rejected because the note changed there too. The cached note is
+/// replaced by the remote one on the next pull; the local content is kept here so it is not lost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub path: String,
+    pub error: String,
+    /// The rejected local content, unless the change was a delete or the file is not text.
+    pub local_content: Option<String>,
+    /// The local and remote changes merged, with markers around the lines both changed, when the
+    /// merge was tried.
+    #[serde(default)]
+    pub merged_content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum PendingChange {
+    Create {
+        path: String,
+        message: String,
+    },
+    Update {
+        path: String,
+        message: String,
+    },
+    Delete {
+        path: String,
+        message: String,
+    },
+    /// Changes to several files that are pushed as one commit.
+    Batch {
+        paths: Vec<String>,
+        message: String,
+    },
+}
+
+impl PendingChange {
+    fn paths(&self) -> &[String] {
+        match self {
+            PendingChange::Create { path, .. }
+            | PendingChange::Update { path, .. }
+            | PendingChange::Delete { path, .. } => std::slice::from_ref(path),
+            PendingChange::Batch { paths, .. } => paths,
+        }
+    }
+
+    fn touches(&self, path: &str) -> bool {
+        self.paths().iter().any(|changed| changed == path)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheState {
+    /// Remote revision of every cached file as of the last sync.
+    remote_revisions: HashMap<String, String>,
+    /// Changes made locally that have not been pushed to the remote yet, oldest first.
+    pending: VecDeque<PendingChange>,
+    last_synced: Option<u64>,
+    /// The remote commit as of the last pull.
+    #[serde(default)]
+    head_commit: Option<String>,
+    #[serde(default)]
+    conflicts: Vec<SyncConflict>,
+    #[serde(skip)]
+    last_error: Option<String>,
+    #[serde(skip)]
+    syncing: bool,
+    /// Whether the front of `pending` is being pushed, so it no longer covers later writes.
+    #[serde(skip)]
+    pushing: bool,
+}
+
+/// Offline-first wrapper around a remote store.
+///
+/// Reads are served from a mirror of the note tree kept in `cache_dir`. Writes are applied to
+/// the mirror and queued; `sync` pushes the queue to the remote and then pulls remote changes.
+pub struct CachedStore<S> {
+    remote: S,
+    files: LocalStore,
+    /// The content of every note read recently, named by its revision.
+    bases_dir: PathBuf,
+    state_path: PathBuf,
+    state: Mutex<CacheState>,
+    sync_lock: tokio::sync::Mutex<()>,
+    /// Held while a local write is applied and queued, and while a pull writes a cached file, so a
+    /// pull never overwrites a change queued after it checked the queue.
+    write_lock: tokio::sync::Mutex<()>,
+    changes_queued: Notify,
+}
+
+impl<S: NoteStore> CachedStore<S> {
+    pub fn open(remote: S, cache_dir: impl Into<PathBuf>) -> Result<Self, NoteServiceError> {
+        let cache_dir = cache_dir.into();
+        let files_dir = cache_dir.join("files");
+        std::fs::create_dir_all(&files_dir)?;
+        let bases_dir = cache_dir.join("bases");
+        std::fs::create_dir_all(&bases_dir)?;
+
+        let state_path = cache_dir.join("state.json");
+        let state = match std::fs::read(&state_path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CacheState::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            remote,
+            files: LocalStore::new(files_dir)?,
+            bases_dir,
+            state_path,
+            state: Mutex::new(state),
+            sync_lock: tokio::sync::Mutex::new(()),
+            write_lock: tokio::sync::Mutex::new(()),
+            changes_queued: Notify::new(),
+        })
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        let state = self.state.lock().unwrap();
+        SyncStatus {
+            state: if state.syncing {
+                SyncState::Syncing
+            } else if state.last_error.is_some() {
+                SyncState::Offline
+            } else {
+                SyncState::Idle
+            },
+            pending_changes: state.pending.len(),
+            last_synced: state.last_synced,
+            last_error: state.last_error.clone(),
+            conflicts: state.conflicts.clone(),
+        }
+    }
+
+    /// Forgets a conflict once the user has dealt with it.
+    pub fn dismiss_conflict(&self, path: &str) -> Result<(), NoteServiceError> {
+        self.state
+            .lock()
+            .unwrap()
+            .conflicts
+            .retain(|conflict| conflict.path != path);
+        self.save()
+    }
+
+    /// Resolves when a local change has been queued since the last call.
+    pub async fn changed(&self) {
+        self.changes_queued.notified().await
+    }
+
+    /// Pushes queued changes to the remote, then pulls remote changes into the cache.
+    /// A create or update the remote rejects is merged with the remote note; changes that still
+    /// cannot be pushed are moved to the conflicts of the status. Other failures are recorded in
+    /// the status and the queue is kept for the next attempt.
+    pub async fn sync(&self) -> SyncStatus {
+        let _guard = self.sync_lock.lock().await;
+        self.state.lock().unwrap().syncing = true;
+
+        let result = match self.push().await {
+            Ok(()) => self.pull().await,
+            Err(e) => Err(e),
+        };
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.syncing = false;
+            match result {
+                Ok(()) => {
+                    state.last_synced = Some(now());
+                    state.last_error = None;
+                }
+                Err(e) => state.last_error = Some(e.to_string()),
+            }
+        }
+        if let Err(e) = self.save() {
+            self.state.lock().unwrap().last_error = Some(e.to_string());
+        }
+        self.prune_bases().await;
+        self.status()
+    }
+
+    async fn push(&self) -> Result<(), NoteServiceError> {
+        loop {
+            let change = {
+                let mut state = self.state.lock().unwrap();
+                let Some(change) = state.pending.front().cloned() else {
+                    return Ok(());
+                };
+                state.pushing = true;
+                change
+            };
+            let result = match self.push_change(&change).await {
+                Err(e) if is_rejection(&e) => self.merge_rejected(&change, e).await,
+                result => result,
+            };
+            self.state.lock().unwrap().pushing = false;
+            let remote_revisions = match result {
+                Ok(remote_revisions) => remote_revisions,
+                Err(e) if is_rejection(&e) => {
+                    self.set_aside(&change, &e).await;
+                    Vec::new()
+                }
+                Err(e) => return Err(e),
+            };
+            {
+                let mut state = self.state.lock().unwrap();
+                state.pending.pop_front();
+                for (path, remote_revision) in remote_revisions {
+                    match remote_revision {
+                        Some(revision) => state.remote_revisions.insert(path, revision),
+                        None => state.remote_revisions.remove(&path),
+                    };
+                }
+            }
+            self.save()?;
+        }
+    }
+
+    /// Records a rejected change as a conflict for each file it touched. Their remote revisions
+    /// are forgotten, so the next pull replaces the cached files with the remote ones.
+    async fn set_aside(&self, change: &PendingChange, error: &NoteServiceError) {
+        let mut conflicts = Vec::new();
+        for path in change.paths() {
+            let local_content = match change {
+                PendingChange::Delete { .. } => None,
+                _ => self.files.read(path).await.ok().flatten(),
+            };
+            let merged_content = match error {
+                NoteServiceError::Conflict(conflict) => conflict.merged_content.clone(),
+                _ => None,
+            };
+            conflicts.push(SyncConflict {
+                path: path.clone(),
+                error: error.to_string(),
+                local_content,
+                merged_content,
+            });
+        }
+        let mut state = self.state.lock().unwrap();
+        for conflict in conflicts {
+            state.remote_revisions.remove(&conflict.path);
+            state.conflicts.retain(|existing| existing.path != conflict.path);
+            state.conflicts.push(conflict);
+        }
+    }
+
+    /// Applies one queued change to the remote and returns the new remote revision of every file
+    /// it touched.
+    async fn push_change(&self, change: &PendingChange) -> Result<Vec<(String, Option<String>)>, NoteServiceError> {
+        let known_revision = |path: &str| self.state.lock().unwrap().remote_revisions.get(path).cloned();
+        match change {
+            PendingChange::Create { path, message } | PendingChange::Update { path, message } => {
+                // The change is pushed with the latest cached content, so a note created and
+                // deleted while offline never reaches the remote.
+                let Some(content) = self.files.read(path).await? else {
+                    return Ok(vec![(path.clone(), known_revision(path))]);
+                };
+                let revision = match known_revision(path) {
+                    Some(revision) => self.remote.update(path, message, &content, &revision).await?,
+                    None => {
+                        self.remote.create(path, message, &content).await?;
+                        self.remote.revision(path).await?
+                    }
+                };
+                Ok(vec![(path.clone(), Some(revision))])
+            }
+            PendingChange::Delete { path, message } => {
+                if let Some(revision) = known_revision(path) {
+                    match self.remote.delete(path, message, &revision).await {
+                        // Already deleted on the remote.
+                        Ok(()) | Err(NoteServiceError::NotFound) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(vec![(path.clone(), None)])
+            }
+            PendingChange::Batch { paths, message } => {
+                let mut changes = Vec::new();
+                for path in paths {
+                    let revision = known_revision(path);
+                    let change = match self.files.read_bytes(path).await? {
+                        Some(content) => StoreChange::file(path.clone(), content),
+                        None if revision.is_some() => StoreChange::Delete { path: path.clone() },
+                        None => continue,
+                    };
+                    // Rejected if the file changed on the remote since it was cached, or was
+                    // created there when it is new here.
+                    changes.push(StoreChange::Expect {
+                        path: path.clone(),
+                        revision,
+                    });
+                    changes.push(change);
+                }
+                if !changes.is_empty() {
+                    self.remote.commit_changes(message, &changes).await?;
+                }
+
+                let mut revisions = Vec::new();
+                for path in paths {
+                    let written = changes.iter().any(|change| {
+                        matches!(change, StoreChange::Put { .. } | StoreChange::PutBytes { .. })
+                            && change.path() == path
+                    });
+                    let revision = match written {
+                        true => Some(self.remote.revision(path).await?),
+                        false => None,
+                    };
+                    revisions.push((path.clone(), revision));
+                }
+                Ok(revisions)
+            }
+        }
+    }
+
+    /// Merges a create or update the remote rejected with the remote note, from the remote
+    /// revision it was based on, and pushes the result. Returns a `Conflict` when both changed
+    /// the same lines, and `error` for other changes.
+    async fn merge_rejected(
+        &self,
+        change: &PendingChange,
+        error: NoteServiceError,
+    ) -> Result<Vec<(String, Option<String>)>, NoteServiceError> {
+        let (PendingChange::Create { path, message } | PendingChange::Update { path, message }) = change else {
+            return Err(error);
+        };
+        let Some(ours) = self.files.read(path).await? else {
+            return Err(error);
+        };
+        let Some(theirs) = self.remote.read(path).await? else {
+            // Deleted on the remote; the local edit brings it back.
+            self.remote.create(path, message, &ours).await?;
+            return Ok(vec![(path.clone(), Some(self.remote.revision(path).await?))]);
+        };
+        let current_revision = self.remote.revision(path).await?;
+        let known_revision = self.state.lock().unwrap().remote_revisions.get(path).cloned();
+        let base = match known_revision {
+            Some(revision) => self.remote.read_revision(path, &revision).await?,
+            // Created on both sides.
+            None => Some(String::new()),
+        };
+        let Some(base) = base else {
+            return Err(NoteServiceError::Conflict(NoteConflict {
+                current_revision,
+                current_content: theirs,
+                ..Default::default()
+            }));
+        };
+
+        let merge = merge::merge(&base, &ours, &theirs);
+        if !merge.is_clean() {
+            return Err(NoteServiceError::Conflict(NoteConflict {
+                current_revision,
+                current_content: theirs,
+                merged_content: Some(merge.content),
+                hunks: merge.conflicts,
+            }));
+        }
+        let revision = self
+            .remote
+            .update(path, message, &merge.content, &current_revision)
+            .await?;
+        {
+            let _write = self.write_lock.lock().await;
+            // Unless it was edited again since, in which case that edit is pushed next.
+            if self.files.read(path).await?.as_deref() == Some(ours.as_str()) {
+                self.files.write_file(path, merge.content.as_bytes()).await?;
+            }
+        }
+        Ok(vec![(path.clone(), Some(revision))])
+    }
+
+    async fn pull(&self) -> Result<(), NoteServiceError> {
+        // Read before listing, so the cache is never older than the commit it is recorded at.
+        let head_commit = self.remote.head_commit().await?;
+        let remote_files = list_files(&self.remote, NOTES_ROOT).await?;
+        let remote_paths: HashSet<&str> = remote_files.iter().map(|entry| entry.path.as_str()).collect();
+
+        for entry in &remote_files {
+            if self.is_pending(&entry.path) {
+                continue;
+            }
+            let cached_revision = self.state.lock().unwrap().remote_revisions.get(&entry.path).cloned();
+            if entry.revision.is_some() && entry.revision == cached_revision {
+                continue;
+            }
+            let content = match self.remote.read_bytes(&entry.path).await {
+                Ok(content) => content.unwrap_or_default(),
+                // Too large to download; the rest of the tree is still cached.
+                Err(NoteServiceError::ContentUnavailable(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            let revision = match &entry.revision {
+                Some(revision) => revision.clone(),
+                None => self.remote.revision(&entry.path).await?,
+            };
+            let _write = self.write_lock.lock().await;
+            // The note may have been changed locally while it was downloaded.
+            if self.is_pending(&entry.path) {
+                continue;
+            }
+            self.files.write_file(&entry.path, &content).await?;
+            self.state
+                .lock()
+                .unwrap()
+                .remote_revisions
+                .insert(entry.path.clone(), revision);
+        }
+
+        for entry in list_files(&self.files, NOTES_ROOT).await? {
+            if remote_paths.contains(entry.path.as_str()) {
+                continue;
+            }
+            let _write = self.write_lock.lock().await;
+            if !self.is_pending(&entry.path) {
+                self.files.remove_file(&entry.path).await?;
+                self.state.lock().unwrap().remote_revisions.remove(&entry.path);
+            }
+        }
+        self.state.lock().unwrap().head_commit = head_commit;
+        Ok(())
+    }
+
+    /// Loads the tree from the remote the first time the cache is used.
+    async fn ensure_loaded(&self) -> Result<(), NoteServiceError> {
+        if self.state.lock().unwrap().last_synced.is_some() {
+            return Ok(());
+        }
+        self.sync().await;
+        let state = self.state.lock().unwrap();
+        match (&state.last_synced, &state.last_error) {
+            (None, Some(error)) => Err(NoteServiceError::Anyhow(format!("Notes are not cached yet: {}", error))),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_pending(&self, path: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .any(|change| change.touches(path))
+    }
+
+    fn enqueue(&self, change: PendingChange) -> Result<(), NoteServiceError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            // A new local change replaces the one that was set aside.
+            state.conflicts.retain(|conflict| !change.touches(&conflict.path));
+            // A queued create, update or batch already pushes the latest cached content, unless it
+            // is being pushed and has already read it.
+            let skip = usize::from(state.pushing);
+            let superseded = match &change {
+                PendingChange::Update { path, .. } => state
+                    .pending
+                    .iter()
+                    .skip(skip)
+                    .any(|queued| !matches!(queued, PendingChange::Delete { .. }) && queued.touches(path)),
+                _ => false,
+            };
+            if !superseded {
+                state.pending.push_back(change);
+            }
+        }
+        self.save()?;
+        self.changes_queued.notify_one();
+        Ok(())
+    }
+
+    /// Removes the content of revisions handed out more than `BASE_RETENTION` ago.
+    async fn prune_bases(&self) {
+        let Ok(mut bases) = tokio::fs::read_dir(&self.bases_dir).await else {
+            return;
+        };
+        while let Ok(Some(base)) = bases.next_entry().await {
+            let expired = base
+                .metadata()
+                .await
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified.elapsed().unwrap_or_default() > BASE_RETENTION);
+            if expired {
+                let _ = tokio::fs::remove_file(base.path()).await;
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), NoteServiceError> {
+        let json = {
+            let state = self.state.lock().unwrap();
+            serde_json::to_vec_pretty(&*state).map_err(|e| NoteServiceError::Anyhow(e.to_string()))?
+        };
+        std::fs::write(&self.state_path, json)?;
+        Ok(())
+    }
+}
+
+/// Whether the remote refused a change because the note changed there, rather than failing to
+/// apply it.
+fn is_rejection(error: &NoteServiceError) -> bool {
+    matches!(
+        error,
+        NoteServiceError::Conflict(_) | NoteServiceError::NoteAlreadyExists | NoteServiceError::NotFound
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl<S: NoteStore> NoteStore for CachedStore<S> {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        self.ensure_loaded().await?;
+        self.files.list(path).await
+    }
+
+    /// The cached content. It is also kept for `BASE_RETENTION` under its revision, so an edit of
+    /// what was read can be merged by `read_revision` later.
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        self.ensure_loaded().await?;
+        let content = self.files.read(path).await?;
+        if let Some(content) = &content {
+            tokio::fs::write(self.bases_dir.join(content_revision(content.as_bytes())), content).await?;
+        }
+        Ok(content)
+    }
+
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        self.ensure_loaded().await?;
+        self.files.read_bytes(path).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        self.ensure_loaded().await?;
+        self.files.exists(path).await
+    }
+
+    async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError> {
+        self.ensure_loaded().await?;
+        let _write = self.write_lock.lock().await;
+        self.files.create(path, message, content).await?;
+        self.enqueue(PendingChange::Create {
+            path: path.to_string(),
+            message: message.to_string(),
+        })
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        let _write = self.write_lock.lock().await;
+        let revision = self.files.update(path, message, content, revision).await?;
+        self.enqueue(PendingChange::Update {
+            path: path.to_string(),
+            message: message.to_string(),
+        })?;
+        Ok(revision)
+    }
+
+    async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        let _write = self.write_lock.lock().await;
+        self.files.delete(path, message, revision).await?;
+        self.enqueue(PendingChange::Delete {
+            path: path.to_string(),
+            message: message.to_string(),
+        })
+    }
+
+    /// A hash of the cached content.
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        let content = self.files.read_bytes(path).await?.ok_or(NoteServiceError::NotFound)?;
+        Ok(content_revision(&content))
+    }
+
+    async fn read_revision(&self, _path: &str, revision: &str) -> Result<Option<String>, NoteServiceError> {
+        if revision.is_empty() || !revision.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        match tokio::fs::read(self.bases_dir.join(revision)).await {
+            Ok(content) => Ok(Some(String::from_utf8_lossy(&content).into_owned())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn commit_changes(&self, message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        self.ensure_loaded().await?;
+        let _write = self.write_lock.lock().await;
+        check_expected(&self.files, changes).await?;
+        for change in changes {
+            match change {
+                StoreChange::Put { path, content } => self.files.write_file(path, content).await?,
+                StoreChange::PutBytes { path, content } => self.files.write_file(path, content).await?,
+                StoreChange::Delete { path } => self.files.remove_file(path).await?,
+                StoreChange::Copy { path, from, .. } => self.files.copy_file(from, path).await?,
+                StoreChange::Expect { .. } => {}
+            }
+        }
+        // Each file is pushed against the remote revision it was cached at.
+        self.enqueue(PendingChange::Batch {
+            paths: changes
+                .iter()
+                .filter(|change| !matches!(change, StoreChange::Expect { .. }))
+                .map(|change| change.path().to_string())
+                .collect(),
+            message: message.to_string(),
+        })
+    }
+
+    /// The remote commit as of the last pull, unless local changes are waiting to be pushed.
+    async fn head_commit(&self) -> Result<Option<String>, NoteServiceError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.head_commit.clone().filter(|_| state.pending.is_empty()))
+    }
+
+    /// Asks the remote, falling back to when the cached copy was written while offline.
+    async fn last_modified(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        match self.remote.last_modified(path).await {
+            Ok(modified) => Ok(modified),
+            Err(_) => self.files.last_modified(path).await,
+        }
+    }
+
+    async fn history(&self, path: &str) -> Result<Vec<NoteCommit>, NoteServiceError> {
+        self.remote.history(path).await
+    }
+
+    async fn read_at_commit(&self, path: &str, commit: &str) -> Result<Option<String>, NoteServiceError> {
+        self.remote.read_at_commit(path, commit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    /// A remote kept in memory that can be taken offline.
+    #[derive(Default)]
+    struct Remote {
+        files: MemoryStore,
+        offline: AtomicBool,
+    }
+
+    impl Remote {
+        fn with_files<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> Arc<Self> {
+            Arc::new(Self {
+                files: MemoryStore::with_files(files),
+                offline: AtomicBool::new(false),
+            })
+        }
+
+        fn reachable(&self) -> Result<&MemoryStore, NoteServiceError> {
+            if self.offline.load(Ordering::SeqCst) {
+                return Err(NoteServiceError::Io("offline".to_string()));
+            }
+            Ok(&self.files)
+        }
+    }
+
+    #[async_trait]
+    impl NoteStore for Remote {
+        async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+            self.reachable()?.list(path).await
+        }
+
+        async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+            self.reachable()?.read(path).await
+        }
+
+        async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+            self.reachable()?.read_bytes(path).await
+        }
+
+        async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+            self.reachable()?.exists(path).await
+        }
+
+        async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError> {
+            self.reachable()?.create(path, message, content).await
+        }
+
+        async fn update(
+            &self,
+            path: &str,
+            message: &str,
+            content: &str,
+            revision: &str,
+        ) -> Result<String, NoteServiceError> {
+            self.reachable()?.update(path, message, content, revision).await
+        }
+
+        async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError> {
+            self.reachable()?.delete(path, message, revision).await
+        }
+
+        async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+            self.reachable()?.revision(path).await
+        }
+
+        async fn commit_changes(&self, message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+            self.reachable()?.commit_changes(message, changes).await
+        }
+
+        async fn read_revision(&self, path: &str, revision: &str) -> Result<Option<String>, NoteServiceError> {
+            self.reachable()?.read_revision(path, revision).await
+        }
+    }
+
+    /// A cache of `remote` in a new temporary directory, loaded with the remote notes.
+    async fn cache(name: &str, remote: &Arc<Remote>) -> CachedStore<Arc<Remote>> {
+        let dir = std::env::temp_dir().join(format!("ashnotes-cache-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = CachedStore::open(remote.clone(), dir).unwrap();
+        assert!(cache.sync().await.last_error.is_none());
+        cache
+    }
+
+    /// Edits the cached note the way a client does, from the revision it read.
+    async fn edit(cache: &CachedStore<Arc<Remote>>, path: &str, content: &str) {
+        cache.read(path).await.unwrap();
+        let revision = cache.revision(path).await.unwrap();
+        cache.update(path, "edit", content, &revision).await.unwrap();
+    }
+
+    /// Edits the remote note directly, as another client would.
+    async fn edit_remote(remote: &Remote, path: &str, content: &str) {
+        let revision = remote.revision(path).await.unwrap();
+        remote.update(path, "edit elsewhere", content, &revision).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn queues_changes_while_offline_and_pushes_them_later() {
+        let remote = Remote::with_files([("notes/a.md", "one")]);
+        let cache = cache("offline", &remote).await;
+        remote.offline.store(true, Ordering::SeqCst);
+
+        edit(&cache, "notes/a.md", "two").await;
+        cache.create("notes/b.md", "create", "b").await.unwrap();
+        let status = cache.sync().await;
+        assert_eq!(status.state, SyncState::Offline);
+        assert_eq!(status.pending_changes, 2);
+        assert_eq!(cache.read("notes/a.md").await.unwrap().as_deref(), Some("two"));
+        assert_eq!(remote.files.read("notes/a.md").await.unwrap().as_deref(), Some("one"));
+
+        remote.offline.store(false, Ordering::SeqCst);
+        let status = cache.sync().await;
+        assert_eq!(status.state, SyncState::Idle);
+        assert_eq!(status.pending_changes, 0);
+        assert_eq!(remote.files.paths(), ["notes/a.md", "notes/b.md"]);
+        assert_eq!(remote.files.read("notes/a.md").await.unwrap().as_deref(), Some("two"));
+    }
+
+    #[tokio::test]
+    async fn merges_local_edits_with_remote_edits() {
+        let remote = Remote::with_files([("notes/a.md", "title\n\nbody\n")]);
+        let cache = cache("merge", &remote).await;
+        edit(&cache, "notes/a.md", "new title\n\nbody\n").await;
+        edit_remote(&remote, "notes/a.md", "title\n\nbody, edited elsewhere\n").await;
+
+        let status = cache.sync().await;
+        assert!(status.conflicts.is_empty());
+        assert_eq!(status.pending_changes, 0);
+        let merged = Some("new title\n\nbody, edited elsewhere\n");
+        assert_eq!(remote.files.read("notes/a.md").await.unwrap().as_deref(), merged);
+        assert_eq!(cache.read("notes/a.md").await.unwrap().as_deref(), merged);
+    }
+
+    #[tokio::test]
+    async fn sets_conflicting_edits_aside_until_dismissed() {
+        let remote = Remote::with_files([("notes/a.md", "line\n"), ("notes/b.md", "b")]);
+        let cache = cache("conflict", &remote).await;
+        edit(&cache, "notes/a.md", "ours\n").await;
+        edit(&cache, "notes/b.md", "b2").await;
+        edit_remote(&remote, "notes/a.md", "theirs\n").await;
+
+        let status = cache.sync().await;
+        assert_eq!(status.pending_changes, 0);
+        let [conflict] = &status.conflicts[..] else {
+            panic!("expected one conflict, got {:?}", status.conflicts);
+        };
+        assert_eq!(conflict.path, "notes/a.md");
+        assert_eq!(conflict.local_content.as_deref(), Some("ours\n"));
+        assert_eq!(
+            conflict.merged_content.as_deref(),
+            Some("<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n")
+        );
+        // The rest of the queue was pushed, and the cache holds the remote note again.
+        assert_eq!(remote.files.read("notes/b.md").await.unwrap().as_deref(), Some("b2"));
+        assert_eq!(cache.read("notes/a.md").await.unwrap().as_deref(), Some("theirs\n"));
+
+        cache.dismiss_conflict("notes/a.md").unwrap();
+        assert!(cache.status().conflicts.is_empty());
+        let reopened = CachedStore::open(remote.clone(), cache.state_path.parent().unwrap()).unwrap();
+        assert!(reopened.status().conflicts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sets_batches_aside_when_a_file_changed_on_the_remote() {
+        let remote = Remote::with_files([("notes/a.md", "a"), ("notes/b.md", "b")]);
+        let cache = cache("batch", &remote).await;
+        let changes = [
+            StoreChange::Put {
+                path: "notes/a.md".to_string(),
+                content: "a2".to_string(),
+            },
+            StoreChange::Delete {
+                path: "notes/b.md".to_string(),
+            },
+        ];
+        cache.commit_changes("batch", &changes).await.unwrap();
+        edit_remote(&remote, "notes/b.md", "b, edited elsewhere").await;
+
+        let status = cache.sync().await;
+        assert_eq!(status.pending_changes, 0);
+        assert_eq!(status.conflicts.len(), 2);
+        assert_eq!(remote.files.read("notes/a.md").await.unwrap().as_deref(), Some("a"));
+        assert_eq!(
+            cache.read("notes/b.md").await.unwrap().as_deref(),
+            Some("b, edited elsewhere")
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use git2::{Commit, Index, IndexEntry, IndexTime, ObjectType, Oid, Repository, Signature, Sort, StatusOptions};
+use tokio::fs;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use super::{check_expected, LocalStore, NoteStore, StoreChange, StoreEntry};
+use crate::note_service::{NoteCommit, NoteConflict, NoteServiceError};
+
+/// Stores notes in a local git working copy, committing every change.
+///
+/// Revisions are git blob SHAs, matching what the GitHub backend reports. git2 calls block, so
+/// they run on blocking threads; a write keeps the repository locked from checking the revision
+/// until its commit is written, so two writes cannot interleave.
+pub struct GitStore {
+    repo: Arc<Mutex<Repository>>,
+    files: LocalStore,
+}
+
+impl GitStore {
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, NoteServiceError> {
+        let root = root.into();
+        let repo = Repository::open(&root)?;
+        if repo.workdir().is_none() {
+            return Err(NoteServiceError::Anyhow(format!(
+                "{} is a bare repository",
+                root.display()
+            )));
+        }
+        Ok(Self {
+            repo: Arc::new(Mutex::new(repo)),
+            files: LocalStore::new(root)?,
+        })
+    }
+
+    async fn lock(&self) -> OwnedMutexGuard<Repository> {
+        self.repo.clone().lock_owned().await
+    }
+
+    /// Runs `work` with the locked repository on a blocking thread.
+    async fn with_repo<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&Repository) -> Result<T, NoteServiceError> + Send + 'static,
+    ) -> Result<T, NoteServiceError> {
+        let repo = self.lock().await;
+        blocking(move || work(&repo)).await
+    }
+
+    /// Commits `paths` as they are in the working copy, releasing `repo` once the commit is
+    /// written.
+    async fn commit(
+        &self,
+        repo: OwnedMutexGuard<Repository>,
+        paths: &[&str],
+        message: &str,
+    ) -> Result<(), NoteServiceError> {
+        let paths = paths
+            .iter()
+            .map(|path| Ok((path.to_string(), self.files.resolve(path)?)))
+            .collect::<Result<Vec<_>, NoteServiceError>>()?;
+        let message = message.to_string();
+        blocking(move || commit(&repo, &paths, &message)).await
+    }
+
+    async fn check_revision(&self, path: &str, revision: &str) -> Result<(), NoteServiceError> {
+        let content = fs::read(self.files.resolve(path)?).await?;
+        let current_revision = blob_revision(&content)?;
+        if current_revision != revision {
+            return Err(NoteServiceError::Conflict(NoteConflict {
+                current_revision,
+                current_content: String::from_utf8_lossy(&content).into_owned(),
+                ..Default::default()
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// Runs blocking git or file work off the async runtime.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, NoteServiceError> + Send + 'static,
+) -> Result<T, NoteServiceError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|err| NoteServiceError::Anyhow(err.to_string()))?
+}
+
+/// Commits `paths` as they are in the working copy on top of `HEAD`. The tree is built from
+/// `HEAD` and `paths` alone, so anything else the user has staged is left staged and out of
+/// the commit.
+fn commit(repo: &Repository, paths: &[(String, PathBuf)], message: &str) -> Result<(), NoteServiceError> {
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(_) => None,
+    };
+
+    let mut index = Index::new()?;
+    if let Some(parent) = &parent {
+        index.read_tree(&parent.tree()?)?;
+    }
+    let mut staged = repo.index()?;
+    for (path, full_path) in paths {
+        let relative = Path::new(path);
+        if full_path.is_file() {
+            let content = std::fs::read(full_path)?;
+            let mode = index.get_path(relative, 0).map_or(0o100644, |entry| entry.mode);
+            index.add(&IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode,
+                uid: 0,
+                gid: 0,
+                file_size: content.len() as u32,
+                id: repo.blob(&content)?,
+                flags: 0,
+                flags_extended: 0,
+                path: path.as_bytes().to_vec(),
+            })?;
+            staged.add_path(relative)?;
+        } else {
+            index.remove_path(relative)?;
+            staged.remove_path(relative)?;
+        }
+    }
+    let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+    // The committed paths are no longer changes in the working copy.
+    staged.write()?;
+
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("ashnotes", "ashnotes@localhost"))?;
+    if parent.as_ref().is_some_and(|parent| parent.tree_id() == tree.id()) {
+        // Nothing changed, e.g. a note overwritten with its own content.
+        return Ok(());
+    }
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+    Ok(())
+}
+
+/// The id of the blob or tree at `path` in `commit`, if it exists there.
+fn entry_id(commit: &Commit, path: &str) -> Option<Oid> {
+    let tree = commit.tree().ok()?;
+    let entry = tree.get_path(Path::new(path.trim_end_matches('/'))).ok()?;
+    Some(entry.id())
+}
+
+fn note_commit(commit: &Commit) -> NoteCommit {
+    let author = commit.author();
+    let date = chrono::DateTime::from_timestamp(commit.time().seconds(), 0)
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_default();
+    NoteCommit {
+        sha: commit.id().to_string(),
+        author: author.name().unwrap_or_default().to_string(),
+        email: author.email().unwrap_or_default().to_string(),
+        date,
+        message: commit.message().unwrap_or_default().trim_end().to_string(),
+    }
+}
+
+fn blob_revision(content: &[u8]) -> Result<String, NoteServiceError> {
+    Ok(Oid::hash_object(ObjectType::Blob, content)?.to_string())
+}
+
+#[async_trait]
+impl NoteStore for GitStore {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        self.files.list(path).await
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        self.files.read(path).await
+    }
+
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        self.files.read_bytes(path).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        self.files.exists(path).await
+    }
+
+    async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError> {
+        let repo = self.lock().await;
+        self.files.create(path, message, content).await?;
+        self.commit(repo, &[path], message).await
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        let repo = self.lock().await;
+        self.check_revision(path, revision).await?;
+        fs::write(self.files.resolve(path)?, content).await?;
+        self.commit(repo, &[path], message).await?;
+        blob_revision(content.as_bytes())
+    }
+
+    async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        let repo = self.lock().await;
+        self.check_revision(path, revision).await?;
+        let full_path = self.files.resolve(path)?;
+        fs::remove_file(&full_path).await?;
+        self.files.remove_empty_parents(&full_path).await;
+        self.commit(repo, &[path], message).await
+    }
+
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        let content = fs::read(self.files.resolve(path)?).await?;
+        blob_revision(&content)
+    }
+
+    async fn commit_changes(&self, message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        let repo = self.lock().await;
+        check_expected(self, changes).await?;
+        for change in changes {
+            match change {
+                StoreChange::Put { path, content } => self.files.write_file(path, content).await?,
+                StoreChange::PutBytes { path, content } => self.files.write_file(path, content).await?,
+                StoreChange::Delete { path } => self.files.remove_file(path).await?,
+                StoreChange::Copy { path, from, .. } => self.files.copy_file(from, path).await?,
+                StoreChange::Expect { .. } => {}
+            }
+        }
+        let paths: Vec<&str> = changes
+            .iter()
+            .filter(|change| !matches!(change, StoreChange::Expect { .. }))
+            .map(StoreChange::path)
+            .collect();
+        self.commit(repo, &paths, message).await
+    }
+
+    async fn read_revision(&self, _path: &str, revision: &str) -> Result<Option<String>, NoteServiceError> {
+        let Ok(oid) = Oid::from_str(revision) else {
+            return Ok(None);
+        };
+        self.with_repo(move |repo| {
+            let Ok(blob) = repo.find_blob(oid) else {
+                return Ok(None);
+            };
+            Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+        })
+        .await
+    }
+
+    async fn head_commit(&self) -> Result<Option<String>, NoteServiceError> {
+        self.with_repo(|repo| {
+            let Ok(head) = repo.head().and_then(|head| head.peel_to_commit()) else {
+                return Ok(None);
+            };
+            // Notes edited outside the app are not part of any commit yet.
+            let mut options = StatusOptions::new();
+            options
+                .pathspec("notes")
+                .include_untracked(true)
+                .recurse_untracked_dirs(true);
+            if !repo.statuses(Some(&mut options))?.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(head.id().to_string()))
+        })
+        .await
+    }
+
+    async fn history(&self, path: &str) -> Result<Vec<NoteCommit>, NoteServiceError> {
+        let path = path.to_string();
+        self.with_repo(move |repo| {
+            let mut walk = repo.revwalk()?;
+            if walk.push_head().is_err() {
+                // No commits yet.
+                return Ok(Vec::new());
+            }
+            walk.set_sorting(Sort::TIME)?;
+
+            let mut commits = Vec::new();
+            for oid in walk {
+                let commit = repo.find_commit(oid?)?;
+                let id = entry_id(&commit, &path);
+                let changed = if commit.parent_count() == 0 {
+                    id.is_some()
+                } else {
+                    commit.parents().all(|parent| entry_id(&parent, &path) != id)
+                };
+                if changed {
+                    commits.push(note_commit(&commit));
+                }
+            }
+            Ok(commits)
+        })
+        .await
+    }
+
+    /// Walks the history once for all `paths`, instead of once per path, stopping as soon as
+    /// every path has been seen changing.
+    async fn last_modified_all(&self, paths: &[String]) -> Result<HashMap<String, String>, NoteServiceError> {
+        let paths = paths.to_vec();
+        self.with_repo(move |repo| {
+            let mut walk = repo.revwalk()?;
+            if walk.push_head().is_err() {
+                // No commits yet.
+                return Ok(HashMap::new());
+            }
+            walk.set_sorting(Sort::TIME)?;
+
+            let mut remaining: HashSet<&str> = paths.iter().map(String::as_str).collect();
+            let mut modified = HashMap::new();
+            for oid in walk {
+                if remaining.is_empty() {
+                    break;
+                }
+                let commit = repo.find_commit(oid?)?;
+                let tree = commit.tree()?;
+                // Like `history`, a merge only changed the files that differ from every parent.
+                let mut changed: Option<HashSet<String>> = None;
+                let parents = commit
+                    .parents()
+                    .map(|parent| parent.tree().map(Some))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let parents = if parents.is_empty() { vec![None] } else { parents };
+                for parent in &parents {
+                    let diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&tree), None)?;
+                    let paths: HashSet<String> = diff
+                        .deltas()
+                        .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+                        .filter_map(|path| path.to_str().map(str::to_string))
+                        .collect();
+                    changed = Some(match changed {
+                        Some(changed) => changed.intersection(&paths).cloned().collect(),
+                        None => paths,
+                    });
+                }
+                for path in changed.unwrap_or_default() {
+                    if remaining.remove(path.as_str()) {
+                        modified.insert(path, note_commit(&commit).date);
+                    }
+                }
+            }
+            Ok(modified)
+        })
+        .await
+    }
+
+    /// `commit` may be any revision git understands, such as an abbreviated SHA. One that names
+    /// no commit is `NotFound`.
+    async fn read_at_commit(&self, path: &str, commit: &str) -> Result<Option<String>, NoteServiceError> {
+        let (path, commit) = (path.to_string(), commit.to_string());
+        self.with_repo(move |repo| {
+            let commit = repo
+                .revparse_single(&commit)
+                .and_then(|object| object.peel_to_commit())
+                .map_err(|_| NoteServiceError::NotFound)?;
+            let Some(id) = entry_id(&commit, &path) else {
+                return Ok(None);
+            };
+            let Ok(blob) = repo.find_blob(id) else {
+                return Ok(None);
+            };
+            Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store over a new repository in a temporary directory of its own.
+    fn store(name: &str) -> GitStore {
+        let root = std::env::temp_dir().join(format!("ashnotes-git-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        Repository::init(&root).unwrap();
+        GitStore::open(root).unwrap()
+    }
+
+    fn messages(commits: &[NoteCommit]) -> Vec<&str> {
+        let mut messages: Vec<_> = commits.iter().map(|commit| commit.message.as_str()).collect();
+        // Commits made within one second have no order by time.
+        messages.sort();
+        messages
+    }
+
+    #[tokio::test]
+    async fn commits_every_change() {
+        let store = store("commits");
+        store.create("notes/a.md", "create a", "one").await.unwrap();
+        let revision = store.revision("notes/a.md").await.unwrap();
+        store.update("notes/a.md", "update a", "two", &revision).await.unwrap();
+        assert_eq!(store.read("notes/a.md").await.unwrap().as_deref(), Some("two"));
+        assert!(store.head_commit().await.unwrap().is_some());
+
+        let revision = store.revision("notes/a.md").await.unwrap();
+        store.delete("notes/a.md", "delete a", &revision).await.unwrap();
+        assert!(!store.exists("notes/a.md").await.unwrap());
+        assert_eq!(
+            messages(&store.history("notes/a.md").await.unwrap()),
+            ["create a", "delete a", "update a"]
+        );
+        store.create("notes/b.md", "create b", "b").await.unwrap();
+        assert!(matches!(
+            store.create("notes/b.md", "create b", "b").await,
+            Err(NoteServiceError::NoteAlreadyExists)
+        ));
+    }
+
+    #[tokio::test]
+    async fn lists_only_the_commits_that_changed_a_note() {
+        let store = store("history");
+        store.create("notes/a.md", "create a", "a").await.unwrap();
+        store.create("notes/b.md", "create b", "b").await.unwrap();
+        let revision = store.revision("notes/a.md").await.unwrap();
+        store.update("notes/a.md", "update a", "a2", &revision).await.unwrap();
+
+        assert_eq!(
+            messages(&store.history("notes/a.md").await.unwrap()),
+            ["create a", "update a"]
+        );
+        assert_eq!(messages(&store.history("notes/b.md").await.unwrap()), ["create b"]);
+        assert!(store.history("notes/missing.md").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_revisions() {
+        let store = store("stale");
+        store.create("notes/a.md", "create a", "one").await.unwrap();
+        let revision = store.revision("notes/a.md").await.unwrap();
+        store.update("notes/a.md", "update a", "two", &revision).await.unwrap();
+
+        let err = store
+            .update("notes/a.md", "update a", "three", &revision)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, NoteServiceError::Conflict(conflict) if conflict.current_content == "two"));
+        assert!(matches!(
+            store.delete("notes/a.md", "delete a", &revision).await,
+            Err(NoteServiceError::Conflict(_))
+        ));
+        assert_eq!(store.read("notes/a.md").await.unwrap().as_deref(), Some("two"));
+        assert_eq!(
+            store.read_revision("notes/a.md", &revision).await.unwrap().as_deref(),
+            Some("one")
+        );
+        assert_eq!(store.history("notes/a.md").await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,789 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use octocrab::models::repos::Content;
+use octocrab::service::middleware::retry::RetryConfig;
+use octocrab::{FromResponse, Octocrab};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OnceCell, Semaphore};
+
+use super::retry::{self, Retry};
+use super::{walk_tree, EntryKind, NoteStore, StoreChange, StoreEntry};
+use crate::note_service::{NoteCommit, NoteConflict, NoteServiceError};
+
+const COMMITS_PER_PAGE: usize = 100;
+/// The most requests a store sends to GitHub at once, so that listing or reading a whole tree
+/// does not trip GitHub's secondary rate limits.
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+/// The mode of a regular file in a git tree.
+const FILE_MODE: &str = "100644";
+
+/// Stores notes in a GitHub repository through the Contents API.
+pub struct GitHubStore {
+    octocrab: Octocrab,
+    /// Permits for `MAX_CONCURRENT_REQUESTS` requests.
+    requests: Semaphore,
+    /// The repository's default branch, once looked up.
+    default_branch: OnceCell<String>,
+    owner: String,
+    repo: String,
+}
+
+/// A GitHub request that failed, after any retries.
+enum RequestError {
+    GitHub(octocrab::Error),
+    RateLimited(Option<chrono::DateTime<chrono::Utc>>),
+}
+
+impl From<RequestError> for NoteServiceError {
+    fn from(err: RequestError) -> Self {
+        match err {
+            RequestError::GitHub(err) => err.into(),
+            RequestError::RateLimited(reset_at) => NoteServiceError::RateLimited {
+                reset_at: reset_at.map(|at| at.to_rfc3339()),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Method {
+    Get,
+    Post,
+    Patch,
+    Put,
+    Delete,
+}
+
+#[derive(Deserialize)]
+struct GitBlob {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct FileContent {
+    sha: String,
+    size: u64,
+    content: Option<String>,
+}
+
+/// The response to a Contents API request that wrote a file.
+#[derive(Deserialize)]
+struct FileWritten {
+    content: GitObject,
+}
+
+/// A Contents API response, which lists a directory but describes a file on its own.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Contents {
+    Dir(Vec<Content>),
+    File(Box<Content>),
+}
+
+#[derive(Deserialize)]
+struct RepoCommit {
+    sha: String,
+    commit: CommitDetails,
+}
+
+#[derive(Deserialize)]
+struct CommitDetails {
+    author: CommitAuthor,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct CommitAuthor {
+    name: String,
+    email: String,
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    default_branch: String,
+}
+
+#[derive(Deserialize)]
+struct GitRef {
+    object: GitObject,
+}
+
+#[derive(Deserialize)]
+struct GitObject {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct GitCommit {
+    tree: GitObject,
+}
+
+#[derive(Deserialize)]
+struct GitTree {
+    tree: Vec<GitTreeItem>,
+    /// Set when the tree was too large to return in full.
+    truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct GitTreeItem {
+    path: String,
+    mode: String,
+    r#type: String,
+    sha: String,
+}
+
+#[derive(Serialize)]
+struct NewTree<'a> {
+    base_tree: &'a str,
+    tree: Vec<TreeEntry<'a>>,
+}
+
+/// An entry of a new tree, with either the new `content`, the `sha` of an uploaded blob, or
+/// `sha: Some(None)`, which is sent as `null` and deletes the path.
+#[derive(Serialize)]
+struct TreeEntry<'a> {
+    path: &'a str,
+    mode: &'a str,
+    r#type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha: Option<Option<&'a str>>,
+}
+
+/// The body of a Contents API request that writes or deletes a file. `sha` is the revision being
+/// replaced, and is left out when creating a file.
+#[derive(Serialize)]
+struct FileChange<'a> {
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct NewBlob<'a> {
+    content: &'a str,
+    encoding: &'static str,
+}
+
+#[derive(Serialize)]
+struct NewCommit<'a> {
+    message: &'a str,
+    tree: &'a str,
+    parents: [&'a str; 1],
+}
+
+#[derive(Serialize)]
+struct RefUpdate<'a> {
+    sha: &'a str,
+}
+
+impl GitHubStore {
+    pub fn new(github_token: String, repo_name: String) -> Result<Self, NoteServiceError> {
+        let parts: Vec<&str> = repo_name.split('/').collect();
+        if parts.len() != 2 {
+            return Err(NoteServiceError::Anyhow(
+                "Invalid repo_name format. Expected 'owner/name'".to_string(),
+            ));
+        }
+        let owner = parts[0].to_string();
+        let repo = parts[1].to_string();
+
+        // Requests are retried by `send`, which waits between attempts.
+        let mut builder = Octocrab::builder();
+        builder.add_retry_config(RetryConfig::None);
+        Ok(Self {
+            octocrab: builder.personal_token(github_token).build()?,
+            requests: Semaphore::new(MAX_CONCURRENT_REQUESTS),
+            default_branch: OnceCell::new(),
+            owner,
+            repo,
+        })
+    }
+
+    fn route(&self, endpoint: &str) -> String {
+        format!("/repos/{}/{}/{}", self.owner, self.repo, endpoint)
+    }
+
+    /// The route of a page of the commits that changed `path`.
+    fn commits_route(&self, path: &str, per_page: usize, page: usize) -> String {
+        self.route(&format!(
+            "commits?path={}&per_page={}&page={}",
+            encode_path(path.trim_end_matches('/')),
+            per_page,
+            page
+        ))
+    }
+
+    /// Sends a request to `route` and parses the response. Rate limited requests and server
+    /// errors are retried as `retry::after_response` decides, and at most
+    /// `MAX_CONCURRENT_REQUESTS` requests are sent at once.
+    async fn send<B, R>(&self, method: Method, route: &str, body: Option<&B>) -> Result<R, RequestError>
+    where
+        B: Serialize + ?Sized + Sync,
+        R: FromResponse,
+    {
+        let mut attempt = 0;
+        loop {
+            let response = {
+                let _permit = self.requests.acquire().await.expect("the semaphore is never closed");
+                match method {
+                    Method::Get => self.octocrab._get(route).await,
+                    Method::Post => self.octocrab._post(route, body).await,
+                    Method::Patch => self.octocrab._patch(route, body).await,
+                    Method::Put => self.octocrab._put(route, body).await,
+                    Method::Delete => self.octocrab._delete(route, body).await,
+                }
+            }
+            .map_err(RequestError::GitHub)?;
+            match retry::after_response(response.status(), response.headers(), attempt) {
+                Retry::After(wait) => {
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                }
+                Retry::RateLimited(reset_at) => return Err(RequestError::RateLimited(reset_at)),
+                Retry::No => {
+                    let response = octocrab::map_github_error(response)
+                        .await
+                        .map_err(RequestError::GitHub)?;
+                    return R::from_response(response).await.map_err(RequestError::GitHub);
+                }
+            }
+        }
+    }
+
+    async fn get<R: FromResponse>(&self, route: &str) -> Result<R, RequestError> {
+        self.send(Method::Get, route, None::<&()>).await
+    }
+
+    async fn post<B: Serialize + Sync, R: FromResponse>(&self, route: &str, body: &B) -> Result<R, RequestError> {
+        self.send(Method::Post, route, Some(body)).await
+    }
+
+    /// The `Conflict` for a write to `path` GitHub rejected because the file changed, with its
+    /// current content, or the error that prevented reading it.
+    async fn conflict(&self, path: &str) -> NoteServiceError {
+        let file: FileContent = match self.get(&self.contents_route(path)).await {
+            Ok(file) => file,
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => return NoteServiceError::NotFound,
+            Err(e) => return e.into(),
+        };
+        let current_content = if content_missing(file.content.as_deref(), file.size) {
+            self.blob(path, &file.sha)
+                .await
+                .map(|content| String::from_utf8_lossy(&content).into_owned())
+        } else {
+            decode_base64(file.content.as_deref().unwrap_or_default())
+        };
+        match current_content {
+            Ok(current_content) => NoteServiceError::Conflict(NoteConflict {
+                current_revision: file.sha,
+                current_content,
+                ..Default::default()
+            }),
+            Err(e) => e,
+        }
+    }
+
+    /// The blobs at `paths` in the tree `tree_sha`, by path, for those that exist. Very large
+    /// trees, which GitHub truncates when listed recursively, are walked down to each path instead.
+    async fn tree_entries(
+        &self,
+        tree_sha: &str,
+        paths: &[&str],
+    ) -> Result<HashMap<String, GitTreeItem>, NoteServiceError> {
+        let tree: GitTree = self
+            .get(&self.route(&format!("git/trees/{}?recursive=1", tree_sha)))
+            .await?;
+        if !tree.truncated {
+            return Ok(tree
+                .tree
+                .into_iter()
+                .filter(|item| item.r#type == "blob" && paths.contains(&item.path.as_str()))
+                .map(|item| (item.path.clone(), item))
+                .collect());
+        }
+
+        let mut entries = HashMap::new();
+        for path in paths {
+            let mut sha = tree_sha.to_string();
+            let mut names = path.split('/').peekable();
+            while let Some(name) = names.next() {
+                let tree: GitTree = self.get(&self.route(&format!("git/trees/{}", sha))).await?;
+                let Some(item) = tree.tree.into_iter().find(|item| item.path == name) else {
+                    break;
+                };
+                match names.peek() {
+                    Some(_) if item.r#type == "tree" => sha = item.sha,
+                    None if item.r#type == "blob" => {
+                        entries.insert(path.to_string(), item);
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn contents_route(&self, path: &str) -> String {
+        self.route(&format!("contents/{}", encode_path(path)))
+    }
+
+    /// Lists the contents of `path`: the entries of a directory, or the file itself.
+    async fn content_items(&self, path: &str) -> Result<Vec<Content>, RequestError> {
+        match self.get(&self.contents_route(path)).await? {
+            Contents::Dir(items) => Ok(items),
+            Contents::File(item) => Ok(vec![*item]),
+        }
+    }
+
+    /// The default branch, looked up once per store.
+    async fn default_branch(&self) -> Result<String, NoteServiceError> {
+        let branch = self
+            .default_branch
+            .get_or_try_init(|| async {
+                let route = format!("/repos/{}/{}", self.owner, self.repo);
+                let repository: Repository = self.get(&route).await?;
+                Ok::<_, NoteServiceError>(repository.default_branch)
+            })
+            .await?;
+        Ok(branch.clone())
+    }
+
+    async fn branch_head(&self, branch: &str) -> Result<GitRef, NoteServiceError> {
+        let route = self.route(&format!("git/ref/heads/{}", branch));
+        Ok(self.get(&route).await?)
+    }
+
+    /// Reads the blob `sha` of the file at `path` through the Git Data API. This also serves
+    /// files the Contents API leaves out for being over its 1 MB limit, up to 100 MB; anything
+    /// larger is `ContentUnavailable`.
+    async fn blob(&self, path: &str, sha: &str) -> Result<Vec<u8>, NoteServiceError> {
+        let route = self.route(&format!("git/blobs/{}", sha));
+        match self.get::<GitBlob>(&route).await {
+            Ok(blob) => decode_base64_bytes(&blob.content),
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) || is_invalid_sha(&e) => {
+                Err(NoteServiceError::NotFound)
+            }
+            Err(RequestError::GitHub(e)) if is_too_large(&e) => {
+                Err(NoteServiceError::ContentUnavailable(path.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Whether the Contents API left out the content of a file, which it does for files over 1 MB.
+fn content_missing(content: Option<&str>, size: u64) -> bool {
+    size > 0 && content.is_none_or(str::is_empty)
+}
+
+fn decode_base64(content: &str) -> Result<String, NoteServiceError> {
+    Ok(String::from_utf8_lossy(&decode_base64_bytes(content)?).into_owned())
+}
+
+fn decode_base64_bytes(content: &str) -> Result<Vec<u8>, NoteServiceError> {
+    STANDARD
+        .decode(content.replace('\n', ""))
+        .map_err(|e| NoteServiceError::GitHub(e.to_string()))
+}
+
+/// Percent-encodes a repository path for use in a URL, keeping the `/` separators.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn is_too_large(err: &octocrab::Error) -> bool {
+    if let octocrab::Error::GitHub { source, .. } = err {
+        return source.status_code == 413 || source.message.to_lowercase().contains("too large");
+    }
+    false
+}
+
+fn is_empty_repository(err: &octocrab::Error) -> bool {
+    matches!(err, octocrab::Error::GitHub { source, .. } if source.status_code == 409)
+}
+
+fn status_code(err: &octocrab::Error) -> Option<u16> {
+    match err {
+        octocrab::Error::GitHub { source, .. } => Some(source.status_code.as_u16()),
+        _ => None,
+    }
+}
+
+fn is_octocrab_not_found(err: &octocrab::Error) -> bool {
+    status_code(err) == Some(404)
+}
+
+/// Whether GitHub rejected a request for naming no file or commit by a malformed SHA, which it
+/// answers with `422` rather than `404`.
+fn is_invalid_sha(err: &octocrab::Error) -> bool {
+    is_unprocessable(err, "sha")
+}
+
+/// Whether GitHub rejected a request for a `ref` that names no commit.
+fn is_unknown_ref(err: &octocrab::Error) -> bool {
+    is_unprocessable(err, "no commit found")
+}
+
+/// Whether GitHub refused to create a file because one exists, which it reports as the `sha` of
+/// the file to replace missing from the request.
+fn is_existing_file(err: &octocrab::Error) -> bool {
+    is_unprocessable(err, "\"sha\" wasn't supplied")
+}
+
+/// Whether `err` is a `422 Unprocessable Entity` whose message contains `text`, in any case.
+fn is_unprocessable(err: &octocrab::Error, text: &str) -> bool {
+    match err {
+        octocrab::Error::GitHub { source, .. } => {
+            source.status_code == 422 && source.message.to_lowercase().contains(&text.to_lowercase())
+        }
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl NoteStore for GitHubStore {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        match self.content_items(path).await {
+            Ok(items) => Ok(Some(
+                items
+                    .into_iter()
+                    .map(|item| StoreEntry {
+                        kind: if item.r#type == "dir" {
+                            EntryKind::Dir
+                        } else {
+                            EntryKind::File
+                        },
+                        path: item.path,
+                        name: item.name,
+                        revision: Some(item.sha),
+                    })
+                    .collect(),
+            )),
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Lists the whole tree with one recursive Git Trees request instead of one Contents request
+    /// per directory. Falls back to the latter when GitHub truncates a very large tree.
+    async fn list_tree(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        // `HEAD` is the default branch, without a request to look it up.
+        let tree: GitTree = match self.get(&self.route("git/trees/HEAD?recursive=1")).await {
+            Ok(tree) => tree,
+            // An empty repository has no tree yet.
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) || is_empty_repository(&e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if tree.truncated {
+            return walk_tree(self, path).await;
+        }
+
+        let path = path.trim_end_matches('/');
+        let mut found = false;
+        let mut entries = Vec::new();
+        for item in tree.tree {
+            let kind = match item.r#type.as_str() {
+                "tree" => EntryKind::Dir,
+                "blob" => EntryKind::File,
+                // Submodules.
+                _ => continue,
+            };
+            if item.path == path {
+                found = true;
+                if kind == EntryKind::Dir {
+                    continue;
+                }
+            } else if !item.path.strip_prefix(path).is_some_and(|rest| rest.starts_with('/')) {
+                continue;
+            }
+            entries.push(StoreEntry {
+                name: item.path.rsplit('/').next().unwrap_or_default().to_string(),
+                path: item.path,
+                kind,
+                revision: Some(item.sha),
+            });
+        }
+        Ok(found.then_some(entries))
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        let item = match self.content_items(path).await {
+            Ok(items) => items.into_iter().next(),
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(item) = item else {
+            return Ok(None);
+        };
+        if item.r#type == "file" && content_missing(item.content.as_deref(), item.size.max(0) as u64) {
+            let content = self.blob(path, &item.sha).await?;
+            return Ok(Some(String::from_utf8_lossy(&content).into_owned()));
+        }
+        Ok(item.decoded_content())
+    }
+
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        let file: FileContent = match self.get(&self.contents_route(path)).await {
+            Ok(file) => file,
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if content_missing(file.content.as_deref(), file.size) {
+            return self.blob(path, &file.sha).await.map(Some);
+        }
+        file.content.map(|content| decode_base64_bytes(&content)).transpose()
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        match self.get::<serde_json::Value>(&self.contents_route(path)).await {
+            Ok(_) => Ok(true),
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError> {
+        let change = FileChange {
+            message,
+            content: Some(STANDARD.encode(content)),
+            sha: None,
+        };
+        match self
+            .send::<_, serde_json::Value>(Method::Put, &self.contents_route(path), Some(&change))
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(RequestError::GitHub(e)) if is_existing_file(&e) => Err(NoteServiceError::NoteAlreadyExists),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        let change = FileChange {
+            message,
+            content: Some(STANDARD.encode(content)),
+            sha: Some(revision),
+        };
+        match self
+            .send::<_, FileWritten>(Method::Put, &self.contents_route(path), Some(&change))
+            .await
+        {
+            Ok(written) => Ok(written.content.sha),
+            Err(RequestError::GitHub(e)) if status_code(&e) == Some(404) => Err(NoteServiceError::NotFound),
+            // `revision` is no longer the file's current SHA.
+            Err(RequestError::GitHub(e)) if status_code(&e) == Some(409) => Err(self.conflict(path).await),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        let change = FileChange {
+            message,
+            content: None,
+            sha: Some(revision),
+        };
+        match self
+            .send::<_, serde_json::Value>(Method::Delete, &self.contents_route(path), Some(&change))
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(RequestError::GitHub(e)) if status_code(&e) == Some(404) => Err(NoteServiceError::NotFound),
+            // `revision` is no longer the file's current SHA.
+            Err(RequestError::GitHub(e)) if status_code(&e) == Some(409) => Err(self.conflict(path).await),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        match self.get::<FileContent>(&self.contents_route(path)).await {
+            Ok(file) => Ok(file.sha),
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => Err(NoteServiceError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes `changes` as one tree and one commit through the Git Data API, then moves the
+    /// default branch to it. The branch update fails if someone pushed in the meantime, so the
+    /// expected revisions, checked against the tree of the commit it is based on, still hold when
+    /// it lands. Binary files are uploaded as blobs first, as tree entries only take text content.
+    /// Copies point at the blob they copy, and are only downloaded and uploaded again when its SHA
+    /// is unknown. Files keep their mode; new ones take the mode of the file they copy, if any.
+    async fn commit_changes(&self, message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        let branch = self.default_branch().await?;
+        let head = self.branch_head(&branch).await?;
+        let parent: GitCommit = self
+            .get(&self.route(&format!("git/commits/{}", head.object.sha)))
+            .await?;
+
+        let mut paths: Vec<&str> = changes.iter().map(StoreChange::path).collect();
+        paths.extend(changes.iter().filter_map(|change| match change {
+            StoreChange::Copy { from, .. } => Some(from.as_str()),
+            _ => None,
+        }));
+        let base = self.tree_entries(&parent.tree.sha, &paths).await?;
+        for change in changes {
+            let StoreChange::Expect { path, revision } = change else {
+                continue;
+            };
+            match (base.get(path.as_str()), revision) {
+                (None, None) => {}
+                (Some(_), None) => return Err(NoteServiceError::NoteAlreadyExists),
+                (None, Some(_)) => return Err(NoteServiceError::NotFound),
+                (Some(entry), Some(revision)) if entry.sha == *revision => {}
+                (Some(_), Some(_)) => return Err(self.conflict(path).await),
+            }
+        }
+        let mode = |path: &str| base.get(path).map(|entry| entry.mode.as_str());
+
+        let mut blobs = Vec::with_capacity(changes.len());
+        for change in changes {
+            let blob = match change {
+                StoreChange::PutBytes { content, .. } => {
+                    let content = STANDARD.encode(content);
+                    let blob = NewBlob {
+                        content: &content,
+                        encoding: "base64",
+                    };
+                    let blob: GitObject = self.post(&self.route("git/blobs"), &blob).await?;
+                    Some(blob.sha)
+                }
+                StoreChange::Copy {
+                    revision: Some(revision),
+                    ..
+                } => Some(revision.clone()),
+                StoreChange::Copy { from, .. } => {
+                    let content = self.read_bytes(from).await?.ok_or(NoteServiceError::NotFound)?;
+                    let content = STANDARD.encode(content);
+                    let blob = NewBlob {
+                        content: &content,
+                        encoding: "base64",
+                    };
+                    let blob: GitObject = self.post(&self.route("git/blobs"), &blob).await?;
+                    Some(blob.sha)
+                }
+                _ => None,
+            };
+            blobs.push(blob);
+        }
+
+        let tree = NewTree {
+            base_tree: &parent.tree.sha,
+            tree: changes
+                .iter()
+                .zip(&blobs)
+                .filter_map(|(change, blob)| {
+                    let (content, sha) = match change {
+                        StoreChange::Put { content, .. } => (Some(content.as_str()), None),
+                        StoreChange::PutBytes { .. } | StoreChange::Copy { .. } => (None, Some(blob.as_deref())),
+                        StoreChange::Delete { .. } => (None, Some(None)),
+                        StoreChange::Expect { .. } => return None,
+                    };
+                    let copied_mode = match change {
+                        StoreChange::Copy { from, .. } => mode(from),
+                        _ => None,
+                    };
+                    Some(TreeEntry {
+                        path: change.path(),
+                        mode: mode(change.path()).or(copied_mode).unwrap_or(FILE_MODE),
+                        r#type: "blob",
+                        content,
+                        sha,
+                    })
+                })
+                .collect(),
+        };
+        let tree: GitObject = self.post(&self.route("git/trees"), &tree).await?;
+
+        let commit = NewCommit {
+            message,
+            tree: &tree.sha,
+            parents: [&head.object.sha],
+        };
+        let commit: GitObject = self.post(&self.route("git/commits"), &commit).await?;
+
+        let update = RefUpdate { sha: &commit.sha };
+        let route = self.route(&format!("git/refs/heads/{}", branch));
+        let _: GitRef = self.send(Method::Patch, &route, Some(&update)).await?;
+        Ok(())
+    }
+
+    async fn head_commit(&self) -> Result<Option<String>, NoteServiceError> {
+        let branch = self.default_branch().await?;
+        let route = self.route(&format!("git/ref/heads/{}", branch));
+        match self.get::<GitRef>(&route).await {
+            Ok(head) => Ok(Some(head.object.sha)),
+            // An empty repository has no branch yet.
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn read_revision(&self, path: &str, revision: &str) -> Result<Option<String>, NoteServiceError> {
+        match self.blob(path, revision).await {
+            Ok(content) => Ok(Some(String::from_utf8_lossy(&content).into_owned())),
+            Err(NoteServiceError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn last_modified(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        let commits: Vec<RepoCommit> = self.get(&self.commits_route(path, 1, 1)).await?;
+        Ok(commits.into_iter().next().map(|commit| commit.commit.author.date))
+    }
+
+    async fn history(&self, path: &str) -> Result<Vec<NoteCommit>, NoteServiceError> {
+        let mut history = Vec::new();
+        for page in 1.. {
+            let commits: Vec<RepoCommit> = self.get(&self.commits_route(path, COMMITS_PER_PAGE, page)).await?;
+            let last_page = commits.len() < COMMITS_PER_PAGE;
+            history.extend(commits.into_iter().map(|commit| NoteCommit {
+                sha: commit.sha,
+                author: commit.commit.author.name,
+                email: commit.commit.author.email,
+                date: commit.commit.author.date,
+                message: commit.commit.message,
+            }));
+            if last_page {
+                break;
+            }
+        }
+        Ok(history)
+    }
+
+    async fn read_at_commit(&self, path: &str, commit: &str) -> Result<Option<String>, NoteServiceError> {
+        let route = self.route(&format!("contents/{}?ref={}", encode_path(path), encode_path(commit)));
+        let file: FileContent = match self.get(&route).await {
+            Ok(file) => file,
+            Err(RequestError::GitHub(e)) if is_octocrab_not_found(&e) => return Ok(None),
+            Err(RequestError::GitHub(e)) if is_unknown_ref(&e) => return Err(NoteServiceError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        if content_missing(file.content.as_deref(), file.size) {
+            let content = self.blob(path, &file.sha).await?;
+            return Ok(Some(String::from_utf8_lossy(&content).into_owned()));
+        }
+        file.content.map(|content| decode_base64(&content)).transpose()
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use super::{check_expected, EntryKind, NoteStore, StoreChange, StoreEntry};
+use crate::note_service::{NoteConflict, NoteServiceError};
+
+/// Stores notes as plain files in a directory on disk.
+///
+/// Revisions are the SHA-256 of the file content, so a stale revision is detected the
+/// same way the GitHub backend rejects a stale SHA.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self, NoteServiceError> {
+        let root = root.into();
+        if !root.is_dir() {
+            return Err(NoteServiceError::Anyhow(format!(
+                "Notes directory {} does not exist",
+                root.display()
+            )));
+        }
+        Ok(Self { root })
+    }
+
+    /// Maps a repository path onto the filesystem, refusing paths that would escape the root.
+    pub(crate) fn resolve(&self, path: &str) -> Result<PathBuf, NoteServiceError> {
+        let relative = Path::new(path.trim_end_matches('/'));
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(NoteServiceError::Anyhow(format!("Invalid note path: {}", path)));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    /// Writes `content` to `path` regardless of its current revision.
+    pub(crate) async fn write_file(&self, path: &str, content: impl AsRef<[u8]>) -> Result<(), NoteServiceError> {
+        let full_path = self.resolve(path)?;
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(full_path, content).await?;
+        Ok(())
+    }
+
+    /// Copies `from` to `path` regardless of the current revision of `path`.
+    pub(crate) async fn copy_file(&self, from: &str, path: &str) -> Result<(), NoteServiceError> {
+        match self.read_bytes(from).await? {
+            Some(content) => self.write_file(path, content).await,
+            None => Err(NoteServiceError::NotFound),
+        }
+    }
+
+    /// Removes `path` regardless of its current revision.
+    pub(crate) async fn remove_file(&self, path: &str) -> Result<(), NoteServiceError> {
+        let full_path = self.resolve(path)?;
+        match fs::remove_file(&full_path).await {
+            Ok(()) => self.remove_empty_parents(&full_path).await,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    async fn check_revision(&self, path: &str, revision: &str) -> Result<(), NoteServiceError> {
+        let content = fs::read(self.resolve(path)?).await?;
+        let current_revision = content_revision(&content);
+        if current_revision != revision {
+            return Err(NoteServiceError::Conflict(NoteConflict {
+                current_revision,
+                current_content: String::from_utf8_lossy(&content).into_owned(),
+                ..Default::default()
+            }));
+        }
+        Ok(())
+    }
+
+    /// Removes directories left empty by a delete, as git does not track empty directories.
+    pub(crate) async fn remove_empty_parents(&self, file: &Path) {
+        let mut dir = file.parent();
+        while let Some(current) = dir {
+            if current == self.root || fs::remove_dir(current).await.is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+}
+
+pub(crate) fn content_revision(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+fn entry_name(path: &str) -> String {
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[async_trait]
+impl NoteStore for LocalStore {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        let full_path = self.resolve(path)?;
+        let metadata = match fs::metadata(&full_path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let path = path.trim_end_matches('/');
+        if metadata.is_file() {
+            return Ok(Some(vec![StoreEntry {
+                path: path.to_string(),
+                name: entry_name(path),
+                kind: EntryKind::File,
+                revision: None,
+            }]));
+        }
+
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(&full_path).await?;
+        while let Some(item) = dir.next_entry().await? {
+            let name = item.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            let kind = if item.file_type().await?.is_dir() {
+                EntryKind::Dir
+            } else {
+                EntryKind::File
+            };
+            entries.push(StoreEntry {
+                path: format!("{}/{}", path, name),
+                name,
+                kind,
+                revision: None,
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Some(entries))
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        match fs::read_to_string(self.resolve(path)?).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        match fs::read(self.resolve(path)?).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        Ok(fs::try_exists(self.resolve(path)?).await?)
+    }
+
+    async fn create(&self, path: &str, _message: &str, content: &str) -> Result<(), NoteServiceError> {
+        let full_path = self.resolve(path)?;
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&full_path)
+            .await
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(NoteServiceError::NoteAlreadyExists),
+            Err(e) => return Err(e.into()),
+        };
+        file.write_all(content.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        _message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        self.check_revision(path, revision).await?;
+        fs::write(self.resolve(path)?, content).await?;
+        Ok(content_revision(content.as_bytes()))
+    }
+
+    async fn delete(&self, path: &str, _message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        self.check_revision(path, revision).await?;
+        let full_path = self.resolve(path)?;
+        fs::remove_file(&full_path).await?;
+        self.remove_empty_parents(&full_path).await;
+        Ok(())
+    }
+
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        let content = fs::read(self.resolve(path)?).await?;
+        Ok(content_revision(&content))
+    }
+
+    /// Writes every change in turn. There are no commits, so a failure part way through leaves
+    /// the earlier changes in place.
+    async fn commit_changes(&self, _message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        check_expected(self, changes).await?;
+        for change in changes {
+            match change {
+                StoreChange::Put { path, content } => self.write_file(path, content).await?,
+                StoreChange::PutBytes { path, content } => self.write_file(path, content).await?,
+                StoreChange::Delete { path } => self.remove_file(path).await?,
+                StoreChange::Copy { path, from, .. } => self.copy_file(from, path).await?,
+                StoreChange::Expect { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The file's modification time, as there is no history to take it from.
+    async fn last_modified(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        let metadata = match fs::metadata(self.resolve(path)?).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let modified: chrono::DateTime<chrono::Utc> = metadata.modified()?.into();
+        Ok(Some(modified.to_rfc3339()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_paths_below_the_root() {
+        let root = std::env::temp_dir();
+        let store = LocalStore::new(&root).unwrap();
+        assert_eq!(store.resolve("notes/work/").unwrap(), root.join("notes/work"));
+        assert_eq!(store.resolve("./notes/a.md").unwrap(), root.join("notes/a.md"));
+    }
+
+    #[test]
+    fn refuses_paths_that_escape_the_root() {
+        let store = LocalStore::new(std::env::temp_dir()).unwrap();
+        for path in ["../secret", "notes/../../secret", "/etc/passwd"] {
+            assert!(
+                matches!(store.resolve(path), Err(NoteServiceError::Anyhow(_))),
+                "{}",
+                path
+            );
+        }
+    }
+}
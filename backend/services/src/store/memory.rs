@@ -0,0 +1,311 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::local::content_revision;
+use super::{EntryKind, NoteStore, StoreChange, StoreEntry};
+use crate::note_service::{NoteConflict, NoteServiceError};
+
+/// Keeps notes in memory, for tests and for trying the service without a repository.
+///
+/// Revisions are the SHA-256 of the content, like `LocalStore`. Every content ever written is
+/// kept by its revision so `read_revision` can return it, and `commit_changes` applies all of
+/// the changes or none of them.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    files: BTreeMap<String, Vec<u8>>,
+    revisions: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryState {
+    fn write(&mut self, path: &str, content: Vec<u8>) {
+        self.revisions.insert(content_revision(&content), content.clone());
+        self.files.insert(path.to_string(), content);
+    }
+
+    fn check_revision(&self, path: &str, revision: &str) -> Result<(), NoteServiceError> {
+        let content = self.files.get(path).ok_or(NoteServiceError::NotFound)?;
+        let current_revision = content_revision(content);
+        if current_revision != revision {
+            return Err(NoteServiceError::Conflict(NoteConflict {
+                current_revision,
+                current_content: String::from_utf8_lossy(content).into_owned(),
+                ..Default::default()
+            }));
+        }
+        Ok(())
+    }
+
+    /// Checks a `StoreChange::Expect` against the files.
+    fn check_expected(&self, path: &str, revision: Option<&str>) -> Result<(), NoteServiceError> {
+        match revision {
+            Some(revision) => self.check_revision(path, revision),
+            None if self.files.contains_key(path) => Err(NoteServiceError::NoteAlreadyExists),
+            None => Ok(()),
+        }
+    }
+
+    fn apply(&mut self, change: &StoreChange) -> Result<(), NoteServiceError> {
+        match change {
+            StoreChange::Put { path, content } => self.write(path, content.clone().into_bytes()),
+            StoreChange::PutBytes { path, content } => self.write(path, content.clone()),
+            StoreChange::Delete { path } => {
+                self.files.remove(path).ok_or(NoteServiceError::NotFound)?;
+            }
+            StoreChange::Copy { path, from, .. } => {
+                let content = self.files.get(from).cloned().ok_or(NoteServiceError::NotFound)?;
+                self.write(path, content);
+            }
+            StoreChange::Expect { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store holding `files`, given as repository paths and their content.
+    pub fn with_files<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let store = Self::new();
+        {
+            let mut state = store.state.lock().unwrap();
+            for (path, content) in files {
+                state.write(path, content.as_bytes().to_vec());
+            }
+        }
+        store
+    }
+
+    /// The paths of every file in the store, in order.
+    pub fn paths(&self) -> Vec<String> {
+        self.state.lock().unwrap().files.keys().cloned().collect()
+    }
+}
+
+fn entry_name(path: &str) -> String {
+    path.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+#[async_trait]
+impl NoteStore for MemoryStore {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        let state = self.state.lock().unwrap();
+        let path = path.trim_end_matches('/');
+        if let Some(content) = state.files.get(path) {
+            return Ok(Some(vec![StoreEntry {
+                path: path.to_string(),
+                name: entry_name(path),
+                kind: EntryKind::File,
+                revision: Some(content_revision(content)),
+            }]));
+        }
+
+        let prefix = if path.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", path)
+        };
+        let mut entries: Vec<StoreEntry> = Vec::new();
+        for (file, content) in state.files.range(prefix.clone()..) {
+            let Some(rest) = file.strip_prefix(&prefix) else {
+                break;
+            };
+            let entry = match rest.split_once('/') {
+                Some((dir, _)) => StoreEntry {
+                    path: format!("{}{}", prefix, dir),
+                    name: dir.to_string(),
+                    kind: EntryKind::Dir,
+                    revision: None,
+                },
+                None => StoreEntry {
+                    path: file.clone(),
+                    name: rest.to_string(),
+                    kind: EntryKind::File,
+                    revision: Some(content_revision(content)),
+                },
+            };
+            if entries.last().map(|last| &last.path) != Some(&entry.path) {
+                entries.push(entry);
+            }
+        }
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Some(entries))
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        match self.read_bytes(path).await? {
+            Some(content) => String::from_utf8(content)
+                .map(Some)
+                .map_err(|_| NoteServiceError::Io(format!("{} is not valid UTF-8", path))),
+            None => Ok(None),
+        }
+    }
+
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        Ok(self.state.lock().unwrap().files.get(path).cloned())
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        Ok(self.list(path).await?.is_some())
+    }
+
+    async fn create(&self, path: &str, _message: &str, content: &str) -> Result<(), NoteServiceError> {
+        let mut state = self.state.lock().unwrap();
+        if state.files.contains_key(path) {
+            return Err(NoteServiceError::NoteAlreadyExists);
+        }
+        state.write(path, content.as_bytes().to_vec());
+        Ok(())
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        _message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        let mut state = self.state.lock().unwrap();
+        state.check_revision(path, revision)?;
+        state.write(path, content.as_bytes().to_vec());
+        Ok(content_revision(content.as_bytes()))
+    }
+
+    async fn delete(&self, path: &str, _message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        let mut state = self.state.lock().unwrap();
+        state.check_revision(path, revision)?;
+        state.files.remove(path);
+        Ok(())
+    }
+
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        let state = self.state.lock().unwrap();
+        let content = state.files.get(path).ok_or(NoteServiceError::NotFound)?;
+        Ok(content_revision(content))
+    }
+
+    /// Applies the changes to a copy of the files, which replaces them only if every change
+    /// succeeded.
+    async fn commit_changes(&self, _message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        let mut state = self.state.lock().unwrap();
+        for change in changes {
+            if let StoreChange::Expect { path, revision } = change {
+                state.check_expected(path, revision.as_deref())?;
+            }
+        }
+        let mut next = MemoryState {
+            files: state.files.clone(),
+            revisions: HashMap::new(),
+        };
+        for change in changes {
+            next.apply(change)?;
+        }
+        state.files = next.files;
+        state.revisions.extend(next.revisions);
+        Ok(())
+    }
+
+    async fn read_revision(&self, _path: &str, revision: &str) -> Result<Option<String>, NoteServiceError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .revisions
+            .get(revision)
+            .map(|content| String::from_utf8_lossy(content).into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lists_files_and_directories() {
+        let store = MemoryStore::with_files([
+            ("notes/README.md", "# Notes"),
+            ("notes/work/todo.md", "- ship"),
+            ("notes/work/plans/q3.md", "plans"),
+        ]);
+
+        let entries = store.list("notes/").await.unwrap().unwrap();
+        let names: Vec<_> = entries.iter().map(|entry| (entry.name.as_str(), entry.kind)).collect();
+        assert_eq!(names, [("README.md", EntryKind::File), ("work", EntryKind::Dir)]);
+
+        let file = store.list("notes/work/todo.md").await.unwrap().unwrap();
+        assert_eq!(file[0].revision.as_deref(), Some(content_revision(b"- ship").as_str()));
+        assert!(store.list("notes/missing").await.unwrap().is_none());
+        assert!(store.list("notes/wo").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_stale_revisions() {
+        let store = MemoryStore::with_files([("notes/a.md", "one")]);
+        let revision = store.revision("notes/a.md").await.unwrap();
+        store.update("notes/a.md", "", "two", &revision).await.unwrap();
+
+        let err = store.update("notes/a.md", "", "three", &revision).await.unwrap_err();
+        assert!(matches!(err, NoteServiceError::Conflict(conflict) if conflict.current_content == "two"));
+        assert_eq!(
+            store.read_revision("notes/a.md", &revision).await.unwrap().as_deref(),
+            Some("one")
+        );
+    }
+
+    #[tokio::test]
+    async fn commits_all_changes_or_none() {
+        let store = MemoryStore::with_files([("notes/a.md", "a")]);
+        let changes = [
+            StoreChange::Put {
+                path: "notes/b.md".to_string(),
+                content: "b".to_string(),
+            },
+            StoreChange::Delete {
+                path: "notes/missing.md".to_string(),
+            },
+        ];
+        assert!(matches!(
+            store.commit_changes("", &changes).await,
+            Err(NoteServiceError::NotFound)
+        ));
+        assert_eq!(store.paths(), ["notes/a.md"]);
+    }
+
+    #[tokio::test]
+    async fn checks_expected_revisions_before_any_change() {
+        let store = MemoryStore::with_files([("notes/a.md", "a"), ("notes/b.md", "b")]);
+        let changes = [
+            StoreChange::Delete {
+                path: "notes/a.md".to_string(),
+            },
+            StoreChange::Expect {
+                path: "notes/b.md".to_string(),
+                revision: None,
+            },
+        ];
+        assert!(matches!(
+            store.commit_changes("", &changes).await,
+            Err(NoteServiceError::NoteAlreadyExists)
+        ));
+
+        let stale = StoreChange::Expect {
+            path: "notes/a.md".to_string(),
+            revision: Some(content_revision(b"old")),
+        };
+        assert!(matches!(
+            store.commit_changes("", &[stale]).await,
+            Err(NoteServiceError::Conflict(_))
+        ));
+        assert_eq!(store.paths(), ["notes/a.md", "notes/b.md"]);
+    }
+}
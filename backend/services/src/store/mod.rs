@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_recursion::async_recursion;
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::note_service::{NoteCommit, NoteConflict, NoteServiceError};
+
+mod cached;
+mod git;
+mod github;
+mod local;
+mod memory;
+mod retry;
+
+pub use cached::{CachedStore, SyncConflict, SyncState, SyncStatus};
+pub use git::GitStore;
+pub use github::GitHubStore;
+pub use local::LocalStore;
+pub use memory::MemoryStore;
+
+/// Where the notes live, as configured through `NOTES_REPO`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotesSource {
+    /// A GitHub repository in `owner/name` form.
+    GitHub(String),
+    /// A directory on disk containing the `notes/` folder. When the directory is a git
+    /// working copy, every change is committed to it.
+    Local(PathBuf),
+}
+
+impl NotesSource {
+    /// Parses a `NOTES_REPO` value. `file://` URLs, absolute paths and paths starting
+    /// with `.` or `~` are local directories; anything else is a GitHub repository.
+    pub fn parse(notes_repo: &str) -> Self {
+        if let Some(path) = notes_repo.strip_prefix("file://") {
+            return NotesSource::Local(PathBuf::from(path));
+        }
+        if let Some(rest) = notes_repo.strip_prefix('~') {
+            if let Some(home) = std::env::var_os("HOME") {
+                return NotesSource::Local(PathBuf::from(home).join(rest.trim_start_matches('/')));
+            }
+        }
+        if notes_repo.starts_with(['.', '/']) || Path::new(notes_repo).is_absolute() {
+            return NotesSource::Local(PathBuf::from(notes_repo));
+        }
+        NotesSource::GitHub(notes_repo.to_string())
+    }
+}
+
+/// Opens the backend for a `NOTES_REPO` value. The token is only needed for GitHub.
+pub fn open(github_token: Option<String>, notes_repo: &str) -> Result<Box<dyn NoteStore>, NoteServiceError> {
+    Ok(match NotesSource::parse(notes_repo) {
+        NotesSource::Local(root) if root.join(".git").exists() => Box::new(GitStore::open(root)?),
+        NotesSource::Local(root) => Box::new(LocalStore::new(root)?),
+        NotesSource::GitHub(repo_name) => {
+            let github_token =
+                github_token.ok_or_else(|| NoteServiceError::Anyhow("GitHub token not set".to_string()))?;
+            Box::new(GitHubStore::new(github_token, repo_name)?)
+        }
+    })
+}
+
+/// Recursively lists every file below `path`, or `path` itself if it is a file.
+pub(crate) async fn list_files<S: NoteStore + ?Sized>(
+    store: &S,
+    path: &str,
+) -> Result<Vec<StoreEntry>, NoteServiceError> {
+    let entries = store.list_tree(path).await?.unwrap_or_default();
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.kind == EntryKind::File)
+        .collect())
+}
+
+/// Lists the tree below `path` one directory at a time, listing the directories at each level
+/// concurrently. This is what `NoteStore::list_tree` does for backends that cannot list a whole
+/// tree at once.
+#[async_recursion]
+pub(crate) async fn walk_tree<S: NoteStore + ?Sized>(
+    store: &S,
+    path: &str,
+) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+    let Some(entries) = store.list(path).await? else {
+        return Ok(None);
+    };
+    let below = join_all(entries.iter().map(|entry| async move {
+        match entry.kind {
+            EntryKind::Dir => walk_tree(store, &entry.path).await,
+            EntryKind::File => Ok(None),
+        }
+    }))
+    .await;
+
+    let mut tree = Vec::new();
+    for (entry, below) in entries.into_iter().zip(below) {
+        let below = below?.unwrap_or_default();
+        tree.push(entry);
+        tree.extend(below);
+    }
+    Ok(Some(tree))
+}
+
+/// Like `walk_tree`, but a directory below `path` that cannot be listed is added to `failed`,
+/// with the error, and left empty instead of failing the whole listing.
+#[async_recursion]
+pub(crate) async fn walk_tree_partial<S: NoteStore + ?Sized>(
+    store: &S,
+    path: &str,
+    failed: &mut Vec<(String, NoteServiceError)>,
+) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+    let Some(entries) = store.list(path).await? else {
+        return Ok(None);
+    };
+    let below = join_all(entries.iter().map(|entry| async move {
+        let mut failed = Vec::new();
+        let below = match entry.kind {
+            EntryKind::Dir => walk_tree_partial(store, &entry.path, &mut failed).await,
+            EntryKind::File => Ok(None),
+        };
+        (below, failed)
+    }))
+    .await;
+
+    let mut tree = Vec::new();
+    for (entry, (below, failed_below)) in entries.into_iter().zip(below) {
+        failed.extend(failed_below);
+        let below = match below {
+            Ok(below) => below.unwrap_or_default(),
+            // Most of the tree would be left out, so the whole listing fails instead.
+            Err(e @ NoteServiceError::RateLimited { .. }) => return Err(e),
+            Err(e) => {
+                failed.push((entry.path.clone(), e));
+                Vec::new()
+            }
+        };
+        tree.push(entry);
+        tree.extend(below);
+    }
+    Ok(Some(tree))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoreEntry {
+    pub path: String,
+    pub name: String,
+    pub kind: EntryKind,
+    /// The file's revision, when the backend reports it while listing.
+    pub revision: Option<String>,
+}
+
+/// One file change in a group written by `NoteStore::commit_changes`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StoreChange {
+    /// Creates the file at `path`, or overwrites it if it exists.
+    Put {
+        path: String,
+        content: String,
+    },
+    /// Like `Put`, for content that is not text, such as an image.
+    PutBytes {
+        path: String,
+        content: Vec<u8>,
+    },
+    Delete {
+        path: String,
+    },
+    /// Copies the file at `from` to `path`, overwriting it if it exists. `revision` is the
+    /// revision `from` was listed with, which lets stores that keep content by revision, such as
+    /// GitHub, copy it without downloading it.
+    Copy {
+        path: String,
+        from: String,
+        revision: Option<String>,
+    },
+    /// Writes nothing, but rejects the whole group unless the file at `path` is at `revision`, or
+    /// does not exist when `revision` is `None`. Checked before any change is applied, so changes
+    /// made against files that have changed since are not written over them.
+    Expect {
+        path: String,
+        revision: Option<String>,
+    },
+}
+
+impl StoreChange {
+    /// Writes `content` as text if it is valid UTF-8, and as bytes otherwise.
+    pub fn file(path: String, content: Vec<u8>) -> Self {
+        match String::from_utf8(content) {
+            Ok(content) => StoreChange::Put { path, content },
+            Err(e) => StoreChange::PutBytes {
+                path,
+                content: e.into_bytes(),
+            },
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            StoreChange::Put { path, .. }
+            | StoreChange::PutBytes { path, .. }
+            | StoreChange::Delete { path }
+            | StoreChange::Copy { path, .. }
+            | StoreChange::Expect { path, .. } => path,
+        }
+    }
+}
+
+/// Checks every `StoreChange::Expect` in `changes` against the files as `store` has them. Fails
+/// with `Conflict` when a file is at another revision, `NotFound` when it is missing and
+/// `NoteAlreadyExists` when it should be.
+pub(crate) async fn check_expected<S: NoteStore + ?Sized>(
+    store: &S,
+    changes: &[StoreChange],
+) -> Result<(), NoteServiceError> {
+    for change in changes {
+        let StoreChange::Expect { path, revision } = change else {
+            continue;
+        };
+        match (store.read_bytes(path).await?, revision) {
+            (None, None) => {}
+            (Some(_), None) => return Err(NoteServiceError::NoteAlreadyExists),
+            (None, Some(_)) => return Err(NoteServiceError::NotFound),
+            (Some(content), Some(revision)) => {
+                let current_revision = store.revision(path).await?;
+                if current_revision != *revision {
+                    return Err(NoteServiceError::Conflict(NoteConflict {
+                        current_revision,
+                        current_content: String::from_utf8_lossy(&content).into_owned(),
+                        ..Default::default()
+                    }));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Storage backend used by `NoteService`.
+///
+/// Paths are relative to the root of the notes repository (e.g. `notes/work/todo.md`),
+/// and revisions are opaque strings that identify the current version of a file.
+#[async_trait]
+pub trait NoteStore: Send + Sync {
+    /// Lists the entries at `path`: the children of a directory, or the entry itself
+    /// when `path` is a file. Returns `None` when nothing exists at `path`.
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError>;
+
+    /// Lists every file and directory below `path` at any depth, each directory followed by its
+    /// contents, or `path` itself if it is a file. Returns `None` when nothing exists at `path`.
+    async fn list_tree(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        walk_tree(self, path).await
+    }
+
+    /// Reads the content of the file at `path`, or `None` if it does not exist.
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError>;
+
+    /// Reads the file at `path` as raw bytes, for files that are not text.
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        Ok(self.read(path).await?.map(String::into_bytes))
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError>;
+
+    async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError>;
+
+    /// Replaces the content of the file at `path`, which must be at `revision`, and returns its
+    /// new revision.
+    async fn update(
+        &self,
+        path: &str,
+        message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError>;
+
+    async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError>;
+
+    /// Returns the current revision of the file at `path`.
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError>;
+
+    /// Applies `changes` as a single commit. Backends without commits apply them one at a time,
+    /// so a failure part way through leaves the earlier changes in place.
+    async fn commit_changes(&self, message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        check_expected(self, changes).await?;
+        for change in changes {
+            match change {
+                StoreChange::Put { path, content } => {
+                    if self.exists(path).await? {
+                        let revision = self.revision(path).await?;
+                        self.update(path, message, content, &revision).await?;
+                    } else {
+                        self.create(path, message, content).await?;
+                    }
+                }
+                StoreChange::PutBytes { .. } => {
+                    return Err(NoteServiceError::Unsupported("Binary files".to_string()));
+                }
+                StoreChange::Delete { path } => {
+                    let revision = self.revision(path).await?;
+                    self.delete(path, message, &revision).await?;
+                }
+                StoreChange::Copy { path, from, .. } => {
+                    let content = self.read(from).await?.ok_or(NoteServiceError::NotFound)?;
+                    if self.exists(path).await? {
+                        let revision = self.revision(path).await?;
+                        self.update(path, message, &content, &revision).await?;
+                    } else {
+                        self.create(path, message, &content).await?;
+                    }
+                }
+                StoreChange::Expect { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the content of `path` as of an earlier `revision`. Returns `None` when the revision
+    /// is unknown or the backend does not keep history.
+    async fn read_revision(&self, _path: &str, _revision: &str) -> Result<Option<String>, NoteServiceError> {
+        Ok(None)
+    }
+
+    /// The commit the notes are at, when they match one exactly. Backends without commits, or
+    /// with changes that have not been committed yet, return `None`.
+    async fn head_commit(&self) -> Result<Option<String>, NoteServiceError> {
+        Ok(None)
+    }
+
+    /// RFC 3339 timestamp of the last change to the file at `path`, if known. Defaults to the
+    /// date of the newest commit in its history.
+    async fn last_modified(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        match self.history(path).await {
+            Ok(history) => Ok(history.into_iter().next().map(|commit| commit.date)),
+            Err(NoteServiceError::Unsupported(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Like `last_modified`, for each of `paths`. Paths whose date is not known are left out.
+    /// Defaults to looking the paths up concurrently.
+    async fn last_modified_all(&self, paths: &[String]) -> Result<HashMap<String, String>, NoteServiceError> {
+        let dates = join_all(paths.iter().map(|path| self.last_modified(path))).await;
+        let mut modified = HashMap::new();
+        for (path, date) in paths.iter().zip(dates) {
+            if let Some(date) = date? {
+                modified.insert(path.clone(), date);
+            }
+        }
+        Ok(modified)
+    }
+
+    /// Lists the commits that changed `path`, newest first.
+    async fn history(&self, _path: &str) -> Result<Vec<NoteCommit>, NoteServiceError> {
+        Err(NoteServiceError::Unsupported("Note history".to_string()))
+    }
+
+    /// Reads the content of `path` as of `commit`, or `None` if it did not exist then.
+    async fn read_at_commit(&self, _path: &str, _commit: &str) -> Result<Option<String>, NoteServiceError> {
+        Err(NoteServiceError::Unsupported("Note history".to_string()))
+    }
+}
+
+#[async_trait]
+impl<T: NoteStore + ?Sized> NoteStore for Box<T> {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        (**self).list(path).await
+    }
+
+    async fn list_tree(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        (**self).list_tree(path).await
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).read(path).await
+    }
+
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        (**self).read_bytes(path).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        (**self).exists(path).await
+    }
+
+    async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError> {
+        (**self).create(path, message, content).await
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        (**self).update(path, message, content, revision).await
+    }
+
+    async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        (**self).delete(path, message, revision).await
+    }
+
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        (**self).revision(path).await
+    }
+
+    async fn commit_changes(&self, message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        (**self).commit_changes(message, changes).await
+    }
+
+    async fn read_revision(&self, path: &str, revision: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).read_revision(path, revision).await
+    }
+
+    async fn head_commit(&self) -> Result<Option<String>, NoteServiceError> {
+        (**self).head_commit().await
+    }
+
+    async fn last_modified(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).last_modified(path).await
+    }
+
+    async fn last_modified_all(&self, paths: &[String]) -> Result<HashMap<String, String>, NoteServiceError> {
+        (**self).last_modified_all(paths).await
+    }
+
+    async fn history(&self, path: &str) -> Result<Vec<NoteCommit>, NoteServiceError> {
+        (**self).history(path).await
+    }
+
+    async fn read_at_commit(&self, path: &str, commit: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).read_at_commit(path, commit).await
+    }
+}
+
+#[async_trait]
+impl<T: NoteStore + ?Sized> NoteStore for Arc<T> {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        (**self).list(path).await
+    }
+
+    async fn list_tree(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        (**self).list_tree(path).await
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).read(path).await
+    }
+
+    async fn read_bytes(&self, path: &str) -> Result<Option<Vec<u8>>, NoteServiceError> {
+        (**self).read_bytes(path).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        (**self).exists(path).await
+    }
+
+    async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError> {
+        (**self).create(path, message, content).await
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        (**self).update(path, message, content, revision).await
+    }
+
+    async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        (**self).delete(path, message, revision).await
+    }
+
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        (**self).revision(path).await
+    }
+
+    async fn commit_changes(&self, message: &str, changes: &[StoreChange]) -> Result<(), NoteServiceError> {
+        (**self).commit_changes(message, changes).await
+    }
+
+    async fn read_revision(&self, path: &str, revision: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).read_revision(path, revision).await
+    }
+
+    async fn head_commit(&self) -> Result<Option<String>, NoteServiceError> {
+        (**self).head_commit().await
+    }
+
+    async fn last_modified(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).last_modified(path).await
+    }
+
+    async fn last_modified_all(&self, paths: &[String]) -> Result<HashMap<String, String>, NoteServiceError> {
+        (**self).last_modified_all(paths).await
+    }
+
+    async fn history(&self, path: &str) -> Result<Vec<NoteCommit>, NoteServiceError> {
+        (**self).history(path).await
+    }
+
+    async fn read_at_commit(&self, path: &str, commit: &str) -> Result<Option<String>, NoteServiceError> {
+        (**self).read_at_commit(path, commit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_local_and_github_sources() {
+        let local = |path: &str| NotesSource::Local(PathBuf::from(path));
+        assert_eq!(NotesSource::parse("file:///srv/notes"), local("/srv/notes"));
+        assert_eq!(NotesSource::parse("/srv/notes"), local("/srv/notes"));
+        assert_eq!(NotesSource::parse("./notes"), local("./notes"));
+        assert_eq!(NotesSource::parse("../notes"), local("../notes"));
+        assert_eq!(
+            NotesSource::parse("octo/notes"),
+            NotesSource::GitHub("octo/notes".to_string())
+        );
+    }
+
+    #[test]
+    fn expands_the_home_directory() {
+        let Some(home) = std::env::var_os("HOME") else {
+            return;
+        };
+        assert_eq!(
+            NotesSource::parse("~/notes"),
+            NotesSource::Local(PathBuf::from(home).join("notes"))
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use http::{HeaderMap, StatusCode};
+
+/// Retries after the first attempt of a request.
+const MAX_RETRIES: u32 = 3;
+/// The wait before the first retry when GitHub does not say how long to wait. It doubles with
+/// every retry after.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+/// The longest wait before a retry. A rate limit that resets later fails right away instead of
+/// holding the request open.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// What to do about a failed GitHub request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Retry {
+    /// Send the request again after the wait.
+    After(Duration),
+    /// Give up because of a rate limit, which resets at the given time if GitHub said when.
+    RateLimited(Option<DateTime<Utc>>),
+    /// Give up and return the error as it is: either it is not worth retrying, or this was the
+    /// last retry of a server error.
+    No,
+}
+
+/// Decides whether to retry a request that got a response with `status` and `headers`, after
+/// `attempt` earlier retries. Rate limits and server errors are retried, waiting as long as
+/// `Retry-After` or `x-ratelimit-reset` ask when GitHub sends them.
+pub(crate) fn after_response(status: StatusCode, headers: &HeaderMap, attempt: u32) -> Retry {
+    let rate_limited = is_rate_limited(status, headers);
+    if !rate_limited && !status.is_server_error() {
+        return Retry::No;
+    }
+    let now = Utc::now();
+    let reset_at = rate_limit_reset(headers);
+    let wait = retry_after(headers)
+        .or_else(|| {
+            reset_at
+                .filter(|_| rate_limited)
+                .map(|at| (at - now).to_std().unwrap_or_default())
+        })
+        .unwrap_or_else(|| backoff(attempt));
+    if attempt < MAX_RETRIES && wait <= MAX_DELAY {
+        Retry::After(wait)
+    } else if rate_limited {
+        Retry::RateLimited(Some(reset_at.unwrap_or(now + wait)))
+    } else {
+        Retry::No
+    }
+}
+
+/// GitHub answers rate limited requests with `429`, or with `403` and either `Retry-After` for a
+/// secondary rate limit or no remaining requests for the primary one. Any other `403` is a
+/// permission error.
+fn is_rate_limited(status: StatusCode, headers: &HeaderMap) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && (headers.contains_key("retry-after") || header(headers, "x-ratelimit-remaining") == Some(0)))
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    header(headers, "retry-after").map(Duration::from_secs)
+}
+
+/// When the primary rate limit resets, from `x-ratelimit-reset` in seconds since the epoch.
+fn rate_limit_reset(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let reset = header(headers, "x-ratelimit-reset")?;
+    DateTime::from_timestamp(i64::try_from(reset).ok()?, 0)
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        for status in [StatusCode::NOT_FOUND, StatusCode::CONFLICT, StatusCode::FORBIDDEN] {
+            assert_eq!(after_response(status, &HeaderMap::new(), 0), Retry::No);
+        }
+    }
+
+    #[test]
+    fn backs_off_on_server_errors() {
+        let status = StatusCode::BAD_GATEWAY;
+        assert_eq!(
+            after_response(status, &HeaderMap::new(), 0),
+            Retry::After(Duration::from_secs(1))
+        );
+        assert_eq!(
+            after_response(status, &HeaderMap::new(), 2),
+            Retry::After(Duration::from_secs(4))
+        );
+        assert_eq!(after_response(status, &HeaderMap::new(), MAX_RETRIES), Retry::No);
+    }
+
+    #[test]
+    fn waits_as_long_as_retry_after_asks() {
+        let headers = headers(&[("retry-after", "7")]);
+        assert_eq!(
+            after_response(StatusCode::FORBIDDEN, &headers, 0),
+            Retry::After(Duration::from_secs(7))
+        );
+        assert_eq!(
+            after_response(StatusCode::TOO_MANY_REQUESTS, &headers, 1),
+            Retry::After(Duration::from_secs(7))
+        );
+    }
+
+    #[test]
+    fn gives_up_on_rate_limits_that_reset_later() {
+        let reset = Utc::now().timestamp() + 3600;
+        let headers = headers(&[
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", &reset.to_string()),
+        ]);
+        assert_eq!(
+            after_response(StatusCode::FORBIDDEN, &headers, 0),
+            Retry::RateLimited(DateTime::from_timestamp(reset, 0))
+        );
+    }
+
+    #[test]
+    fn reports_when_an_exhausted_rate_limit_resets() {
+        let status = StatusCode::TOO_MANY_REQUESTS;
+        let before = Utc::now();
+        let Retry::RateLimited(Some(reset_at)) = after_response(status, &HeaderMap::new(), MAX_RETRIES) else {
+            panic!("expected a rate limit");
+        };
+        assert!(reset_at >= before + BASE_DELAY * 8);
+    }
+}
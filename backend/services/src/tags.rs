@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::metadata;
+
+/// A tag and the number of notes tagged with it or with a tag nested below it, so `project`
+/// counts the notes tagged `project/alpha` too.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// A note found by its tag.
+#[derive(Serialize, Debug, Clone)]
+pub struct TaggedNote {
+    pub id: String,
+    pub name: String,
+    /// The note's tags that matched, which differ from the requested tag when they are nested
+    /// below it.
+    pub tags: Vec<String>,
+}
+
+/// The tags of a note: the `tags` of its front matter and the `#tags` written in its body,
+/// lowercased and without the `#`.
+pub fn note_tags(content: &str) -> HashSet<String> {
+    let (metadata, body) = metadata::parse(content);
+    let mut tags = inline_tags(body);
+    if let Some(metadata) = metadata {
+        tags.extend(metadata.tags.iter().filter_map(|tag| normalize(tag)));
+    }
+    tags
+}
+
+/// `tag` lowercased, without a leading `#` or surrounding slashes, or `None` if nothing is left.
+pub fn normalize(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_start_matches('#').trim_matches('/').to_lowercase();
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// Whether `tag` is `parent` or nested below it.
+pub fn is_within(tag: &str, parent: &str) -> bool {
+    tag.strip_prefix(parent)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// `tag` and the tags it is nested below, e.g. `project/alpha` and `project`.
+pub fn with_parents(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices('/')
+        .map(|(index, _)| &tag[..index])
+        .chain(std::iter::once(tag))
+}
+
+/// The `#tags` written in `body`, outside of fenced code blocks.
+fn inline_tags(body: &str) -> HashSet<String> {
+    let mut in_code = false;
+    body.lines()
+        .filter(|line| {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+                return false;
+            }
+            !in_code
+        })
+        .flat_map(str::split_whitespace)
+        .filter_map(|word| word.strip_prefix('#'))
+        .map(|tag| {
+            let end = tag
+                .find(|c: char| !c.is_alphanumeric() && !matches!(c, '-' | '_' | '/'))
+                .unwrap_or(tag.len());
+            &tag[..end]
+        })
+        // Skip issue references like `#12`.
+        .filter(|tag| !tag.chars().all(|c| c.is_ascii_digit()))
+        .filter_map(normalize)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(tags: HashSet<String>) -> Vec<String> {
+        let mut tags: Vec<_> = tags.into_iter().collect();
+        tags.sort();
+        tags
+    }
+
+    #[test]
+    fn collects_front_matter_and_inline_tags() {
+        let content = "---\ntags: [Work, '#q3/']\n---\nSee #Project/Alpha, #idea. and #12\n";
+        assert_eq!(sorted(note_tags(content)), ["idea", "project/alpha", "q3", "work"]);
+    }
+
+    #[test]
+    fn skips_code_blocks_and_headings() {
+        let content = "# Heading\n```\n#include <stdio.h>\n```\n#kept";
+        assert_eq!(sorted(note_tags(content)), ["kept"]);
+    }
+
+    #[test]
+    fn nests_tags_by_slash() {
+        assert!(is_within("project/alpha", "project"));
+        assert!(is_within("project", "project"));
+        assert!(!is_within("projects", "project"));
+        assert_eq!(with_parents("a/b/c").collect::<Vec<_>>(), ["a", "a/b", "a/b/c"]);
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use services::note_service::{CreateNote, NoteService, NoteServiceError, UpdateNote};
+use services::store::{MemoryStore, NoteStore, StoreEntry};
+
+type Service = NoteService<Arc<MemoryStore>>;
+
+/// A service over `files`, and its store to inspect the files it leaves.
+fn service(files: &[(&str, &str)]) -> (Service, Arc<MemoryStore>) {
+    let store = Arc::new(MemoryStore::with_files(files.iter().copied()));
+    (NoteService::with_store(store.clone(), "tests".to_string()), store)
+}
+
+fn create(path: &str, content: &str) -> CreateNote {
+    CreateNote {
+        path: path.to_string(),
+        content: content.to_string(),
+        metadata: None,
+    }
+}
+
+fn update(content: &str, revision: Option<String>) -> UpdateNote {
+    UpdateNote {
+        content: content.to_string(),
+        revision,
+        metadata: None,
+    }
+}
+
+async fn content(service: &Service, id: &str) -> Option<String> {
+    service.get_note(id).await.unwrap().and_then(|note| note.content)
+}
+
+#[tokio::test]
+async fn creates_notes_with_their_categories() {
+    let (service, _) = service(&[("notes/README.md", "# Notes")]);
+    service.create_note(&create("work/plans/q3.md", "ship")).await.unwrap();
+
+    assert_eq!(content(&service, "work/plans/q3.md").await.as_deref(), Some("ship"));
+    assert_eq!(content(&service, "work/README.md").await.as_deref(), Some("# work"));
+    assert_eq!(
+        content(&service, "work/plans/README.md").await.as_deref(),
+        Some("# plans")
+    );
+    assert!(matches!(
+        service.create_note(&create("work/plans/q3.md", "again")).await,
+        Err(NoteServiceError::NoteAlreadyExists)
+    ));
+}
+
+#[tokio::test]
+async fn updates_and_deletes_notes() {
+    let (service, _) = service(&[("notes/a.md", "one")]);
+    let saved = service.update_note("a.md", &update("two", None)).await.unwrap();
+    assert_eq!(content(&service, "a.md").await.as_deref(), Some("two"));
+    let note = service.get_note("a.md").await.unwrap().unwrap();
+    assert_eq!(note.revision, Some(saved.revision));
+
+    service.delete_note("a.md").await.unwrap();
+    assert!(service.get_note("a.md").await.unwrap().is_none());
+    assert!(matches!(
+        service.delete_note("a.md").await,
+        Err(NoteServiceError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn merges_edits_made_since_the_note_was_read() {
+    let (service, _) = service(&[("notes/a.md", "title\n\nbody\n")]);
+    let read = service.get_note("a.md").await.unwrap().unwrap().revision;
+    service
+        .update_note("a.md", &update("title\n\nbody, edited elsewhere\n", None))
+        .await
+        .unwrap();
+
+    let saved = service
+        .update_note("a.md", &update("new title\n\nbody\n", read))
+        .await
+        .unwrap();
+    assert_eq!(saved.content, "new title\n\nbody, edited elsewhere\n");
+    assert_eq!(content(&service, "a.md").await, Some(saved.content));
+}
+
+#[tokio::test]
+async fn reports_overlapping_edits_as_conflicts() {
+    let (service, _) = service(&[("notes/a.md", "line\n")]);
+    let read = service.get_note("a.md").await.unwrap().unwrap().revision;
+    service.update_note("a.md", &update("theirs\n", None)).await.unwrap();
+
+    let Err(NoteServiceError::Conflict(conflict)) = service.update_note("a.md", &update("ours\n", read)).await else {
+        panic!("expected a conflict");
+    };
+    assert_eq!(conflict.current_content, "theirs\n");
+    assert_eq!(
+        conflict.merged_content.as_deref(),
+        Some("<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n")
+    );
+    assert_eq!(content(&service, "a.md").await.as_deref(), Some("theirs\n"));
+}
+
+#[tokio::test]
+async fn moves_categories() {
+    let (service, store) = service(&[
+        ("notes/work/README.md", "# work"),
+        ("notes/work/a.md", "a"),
+        ("notes/work/sub/b.md", "b"),
+    ]);
+    service.move_note("work", "archive/work").await.unwrap();
+
+    assert_eq!(
+        store.paths(),
+        [
+            "notes/archive/README.md",
+            "notes/archive/work/README.md",
+            "notes/archive/work/a.md",
+            "notes/archive/work/sub/b.md",
+        ]
+    );
+    assert!(matches!(
+        service.move_note("archive", "archive/inner").await,
+        Err(NoteServiceError::InvalidPath(_))
+    ));
+    assert!(matches!(
+        service.move_note("archive/", "archive").await,
+        Err(NoteServiceError::InvalidPath(_))
+    ));
+    assert!(matches!(
+        service.move_note("missing", "elsewhere").await,
+        Err(NoteServiceError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn copies_notes_unless_the_target_exists() {
+    let (service, _) = service(&[("notes/a.md", "a"), ("notes/b.md", "b")]);
+    service.copy_note("a.md", "c.md", false).await.unwrap();
+    assert_eq!(content(&service, "c.md").await.as_deref(), Some("a"));
+
+    assert!(matches!(
+        service.copy_note("a.md", "b.md", false).await,
+        Err(NoteServiceError::NoteAlreadyExists)
+    ));
+    service.copy_note("a.md", "b.md", true).await.unwrap();
+    assert_eq!(content(&service, "b.md").await.as_deref(), Some("a"));
+}
+
+#[tokio::test]
+async fn refuses_to_copy_categories_into_themselves() {
+    let (service, store) = service(&[("notes/work/a.md", "a")]);
+    for to in ["work", "work/", "work/copy"] {
+        assert!(matches!(
+            service.copy_note("work", to, true).await,
+            Err(NoteServiceError::InvalidPath(_))
+        ));
+    }
+    assert_eq!(store.paths(), ["notes/work/a.md"]);
+    service.copy_note("work", "workshop", false).await.unwrap();
+}
+
+#[tokio::test]
+async fn adds_attachments_together_or_not_at_all() {
+    let (service, store) = service(&[("notes/work/README.md", "# work"), ("notes/work/assets/a.png", "a")]);
+    let files = |names: &[&str]| names.iter().map(|name| (name.to_string(), b"new".to_vec())).collect();
+
+    let result = service.add_attachments("work", files(&["b.png", "a.png"]), false).await;
+    assert!(matches!(result, Err(NoteServiceError::NoteAlreadyExists)));
+    assert_eq!(store.paths(), ["notes/work/README.md", "notes/work/assets/a.png"]);
+
+    let attachments = service
+        .add_attachments("work", files(&["b.png", "a.png"]), true)
+        .await
+        .unwrap();
+    let links: Vec<_> = attachments.iter().map(|attachment| attachment.link.as_str()).collect();
+    assert_eq!(links, ["assets/b.png", "assets/a.png"]);
+    assert_eq!(
+        store.paths(),
+        [
+            "notes/work/README.md",
+            "notes/work/assets/a.png",
+            "notes/work/assets/b.png"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn deletes_trees_in_one_commit() {
+    let (service, store) = service(&[
+        ("notes/work/README.md", "# work"),
+        ("notes/work/a.md", "a"),
+        ("notes/home.md", "home"),
+    ]);
+    let report = service.delete_tree("work").await.unwrap();
+
+    assert_eq!(report.deleted, ["notes/work/README.md", "notes/work/a.md"]);
+    assert!(report.failed.is_empty());
+    assert_eq!(store.paths(), ["notes/home.md"]);
+}
+
+/// A store without commits whose listings and deletes of `broken` fail, to stop a walk or a
+/// deletion part way.
+struct FailingStore {
+    inner: Arc<MemoryStore>,
+    broken: &'static str,
+}
+
+#[async_trait]
+impl NoteStore for FailingStore {
+    async fn list(&self, path: &str) -> Result<Option<Vec<StoreEntry>>, NoteServiceError> {
+        if path == self.broken {
+            return Err(NoteServiceError::Io("disk full".to_string()));
+        }
+        self.inner.list(path).await
+    }
+
+    async fn read(&self, path: &str) -> Result<Option<String>, NoteServiceError> {
+        self.inner.read(path).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, NoteServiceError> {
+        self.inner.exists(path).await
+    }
+
+    async fn create(&self, path: &str, message: &str, content: &str) -> Result<(), NoteServiceError> {
+        self.inner.create(path, message, content).await
+    }
+
+    async fn update(
+        &self,
+        path: &str,
+        message: &str,
+        content: &str,
+        revision: &str,
+    ) -> Result<String, NoteServiceError> {
+        self.inner.update(path, message, content, revision).await
+    }
+
+    async fn delete(&self, path: &str, message: &str, revision: &str) -> Result<(), NoteServiceError> {
+        if path == self.broken {
+            return Err(NoteServiceError::Io("disk full".to_string()));
+        }
+        self.inner.delete(path, message, revision).await
+    }
+
+    async fn revision(&self, path: &str) -> Result<String, NoteServiceError> {
+        self.inner.revision(path).await
+    }
+}
+
+#[tokio::test]
+async fn reports_the_files_a_failed_deletion_left() {
+    let files = Arc::new(MemoryStore::with_files([
+        ("notes/work/a.md", "a"),
+        ("notes/work/b.md", "b"),
+        ("notes/work/c.md", "c"),
+    ]));
+    let store = FailingStore {
+        inner: files.clone(),
+        broken: "notes/work/b.md",
+    };
+    let service = NoteService::with_store(store, "tests".to_string());
+    let report = service.delete_tree("work").await.unwrap();
+
+    assert_eq!(report.deleted, ["notes/work/a.md"]);
+    let failed: Vec<_> = report.failed.iter().map(|failure| failure.path.as_str()).collect();
+    assert_eq!(failed, ["notes/work/b.md", "notes/work/c.md"]);
+    assert_eq!(files.paths(), ["notes/work/b.md", "notes/work/c.md"]);
+}
+
+#[tokio::test]
+async fn gets_categories_with_the_parts_that_failed_to_load() {
+    let files = Arc::new(MemoryStore::with_files([
+        ("notes/work/a.md", "a"),
+        ("notes/work/old/b.md", "b"),
+    ]));
+    let store = FailingStore {
+        inner: files,
+        broken: "notes/work/old",
+    };
+    let service = NoteService::with_store(store, "tests".to_string());
+    let note = service.get_note("work").await.unwrap().unwrap();
+
+    let children: Vec<_> = note.children.unwrap().into_iter().map(|child| child.id).collect();
+    assert_eq!(children, ["notes/work/a.md", "notes/work/old"]);
+    let warnings: Vec<_> = note.warnings.iter().map(|warning| warning.path.as_str()).collect();
+    assert_eq!(warnings, ["notes/work/old"]);
+}
@@ -1,39 +1,70 @@
+use std::sync::Arc;
+
 use axum::{
     async_trait,
-    extract::{FromRequestParts, Path},
-    http::{request::Parts, StatusCode},
-    response::IntoResponse,
+    body::{Body, Bytes},
+    extract::{FromRequestParts, Multipart, Path, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use services::note_service::{CreateNote, Note, NoteService, NoteServiceError, UpdateNote};
+use serde::{de::DeserializeOwned, Deserialize};
+use services::diff::NoteDiff;
+use services::links::{Backlink, BrokenLinks};
+use services::note_service::{
+    Attachment, CopyNote, CreateNote, DeleteReport, MoveNote, NoteCommit, NoteService, NoteServiceError, NoteTree,
+    RestoreNote, UpdateNote,
+};
+use services::search::SearchHit;
+use services::store::{self, NoteStore, NotesSource};
+use services::tags::{TagCount, TaggedNote};
+
+use crate::cache::NotesKey;
+use crate::{AppState, Notes};
 
 pub struct ApiHeaders {
-    pub github_token: String,
+    pub github_token: Option<String>,
     pub notes_repo: String,
     pub app_identifier: String,
 }
 
 #[async_trait]
-impl<S> FromRequestParts<S> for ApiHeaders
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<Arc<AppState>> for ApiHeaders {
     type Rejection = (StatusCode, String);
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    /// A local notes directory is only used when the server is configured with one, as a
+    /// client-chosen path would give access to any directory on the server.
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
         let github_token = parts
             .headers
             .get("GITHUB_TOKEN")
             .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string())
-            .ok_or_else(|| (StatusCode::BAD_REQUEST, "GITHUB_TOKEN header is missing".to_string()))?;
+            .map(|s| s.to_string());
 
-        let notes_repo = parts
+        let notes_repo = match parts
             .headers
             .get("NOTES_REPO")
             .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string())
-            .ok_or_else(|| (StatusCode::BAD_REQUEST, "NOTES_REPO header is missing".to_string()))?;
+            .filter(|v| !v.is_empty())
+        {
+            Some(notes_repo) => {
+                if let NotesSource::Local(_) = NotesSource::parse(notes_repo) {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "NOTES_REPO must be a GitHub repository; local directories are configured on the server"
+                            .to_string(),
+                    ));
+                }
+                if github_token.is_none() {
+                    return Err((StatusCode::BAD_REQUEST, "GITHUB_TOKEN header is missing".to_string()));
+                }
+                notes_repo.to_string()
+            }
+            None => state
+                .local_notes_repo
+                .clone()
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, "NOTES_REPO header is missing".to_string()))?,
+        };
 
         let app_identifier = parts
             .headers
@@ -50,68 +81,408 @@ where
     }
 }
 
-pub fn get_note_service(headers: &ApiHeaders) -> Result<NoteService, NoteServiceError> {
-    NoteService::new(
-        headers.github_token.clone(),
-        headers.notes_repo.clone(),
-        headers.app_identifier.clone(),
-    )
+pub type ApiNoteService = NoteService<Arc<dyn NoteStore>>;
+
+pub fn get_note_service(state: &AppState, headers: &ApiHeaders) -> Result<ApiNoteService, NoteServiceError> {
+    let key = NotesKey::new(headers.github_token.as_deref(), &headers.notes_repo);
+    let notes = state.notes.lock().unwrap().get_or_try_insert(key, || {
+        Ok::<_, NoteServiceError>(Notes {
+            store: store::open(headers.github_token.clone(), &headers.notes_repo)?.into(),
+            search_index: Arc::default(),
+        })
+    })?;
+    let note_service = NoteService::with_store(notes.store, headers.app_identifier.clone());
+    Ok(note_service.with_search_index(notes.search_index))
+}
+
+fn error_response(err: NoteServiceError) -> Response {
+    match err {
+        NoteServiceError::NoteAlreadyExists => {
+            (StatusCode::CONFLICT, "Note with this path already exists".to_string()).into_response()
+        }
+        NoteServiceError::NotFound => (StatusCode::NOT_FOUND, "Note not found".to_string()).into_response(),
+        NoteServiceError::Conflict(conflict) => (StatusCode::CONFLICT, Json(conflict)).into_response(),
+        NoteServiceError::Unsupported(_) => (StatusCode::NOT_IMPLEMENTED, err.to_string()).into_response(),
+        NoteServiceError::InvalidQuery(_)
+        | NoteServiceError::InvalidAttachment(_)
+        | NoteServiceError::InvalidPath(_) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+        NoteServiceError::ContentUnavailable(_) => (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response(),
+        NoteServiceError::RateLimited { .. } => (StatusCode::TOO_MANY_REQUESTS, err.to_string()).into_response(),
+        e => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    /// Include the front matter of every note.
+    #[serde(default)]
+    pub metadata: bool,
+}
+
+pub async fn list_notes(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<NoteTree>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
+    let notes = match query.metadata {
+        true => note_service.get_all_notes_with_metadata().await,
+        false => note_service.get_all_notes().await,
+    };
+    notes.map(Json).map_err(error_response)
+}
+
+#[derive(Deserialize)]
+pub struct NoteQuery {
+    /// A commit SHA from the note's history, to get the note as it was in that commit.
+    pub rev: Option<String>,
+}
+
+pub async fn get_note(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(path): Path<String>,
+    Query(query): Query<NoteQuery>,
+) -> Response {
+    let note_service = match get_note_service(&state, &headers) {
+        Ok(service) => service,
+        Err(e) => return error_response(e),
+    };
+    let note = match query.rev {
+        Some(rev) => note_service.get_note_at(&path, &rev).await,
+        None => note_service.get_note(&path).await.map_err(NoteServiceError::from),
+    };
+    match note {
+        Ok(Some(note)) => Json(note).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Note not found".to_string()).into_response(),
+        Err(e) => error_response(e),
+    }
 }
 
-pub async fn list_notes(headers: ApiHeaders) -> Result<Json<Vec<Note>>, impl IntoResponse> {
-    let note_service = get_note_service(&headers).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+pub async fn get_note_history(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(path): Path<String>,
+) -> Result<Json<Vec<NoteCommit>>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
     note_service
-        .get_all_notes()
+        .get_note_history(&path)
         .await
         .map(Json)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        .map_err(error_response)
 }
 
-pub async fn get_note(headers: ApiHeaders, Path(path): Path<String>) -> Result<Json<Note>, impl IntoResponse> {
-    let note_service = get_note_service(&headers).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    match note_service.get_note(&path).await {
-        Ok(Some(note)) => Ok(Json(note)),
-        Ok(None) => Err((StatusCode::NOT_FOUND, "Note not found".to_string())),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
-    }
+pub async fn get_backlinks(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(path): Path<String>,
+) -> Result<Json<Vec<Backlink>>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
+    note_service
+        .get_backlinks(&path)
+        .await
+        .map(Json)
+        .map_err(error_response)
 }
 
-pub async fn create_note(headers: ApiHeaders, Json(payload): Json<CreateNote>) -> impl IntoResponse {
-    let note_service = match get_note_service(&headers) {
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    /// The commit to diff from.
+    pub from: String,
+    /// The commit to diff to. Without it, the diff is against the current content.
+    pub to: Option<String>,
+}
+
+pub async fn diff_note(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(path): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<NoteDiff>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
+    note_service
+        .diff_note(&path, &query.from, query.to.as_deref())
+        .await
+        .map(Json)
+        .map_err(error_response)
+}
+
+pub async fn create_note(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Json(payload): Json<CreateNote>,
+) -> Response {
+    let note_service = match get_note_service(&state, &headers) {
         Ok(service) => service,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Err(e) => return error_response(e),
     };
     match note_service.create_note(&payload).await {
-        Ok(_) => (StatusCode::CREATED, "Note created".to_string()),
-        Err(NoteServiceError::NoteAlreadyExists) => {
-            (StatusCode::CONFLICT, "Note with this path already exists".to_string())
-        }
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Ok(_) => (StatusCode::CREATED, "Note created".to_string()).into_response(),
+        Err(e) => error_response(e),
     }
 }
 
 pub async fn update_note(
+    State(state): State<Arc<AppState>>,
     headers: ApiHeaders,
     Path(path): Path<String>,
     Json(payload): Json<UpdateNote>,
-) -> impl IntoResponse {
-    let note_service = match get_note_service(&headers) {
+) -> Response {
+    let note_service = match get_note_service(&state, &headers) {
         Ok(service) => service,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Err(e) => return error_response(e),
     };
     match note_service.update_note(&path, &payload).await {
-        Ok(_) => (StatusCode::OK, "Note updated".to_string()),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Ok(saved) => Json(saved).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    /// Replace attachments that already exist instead of failing.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Handles `POST /api/attachments/*path`: stores every file of a `multipart/form-data` body in
+/// the `assets/` folder of the category at `path`, or of `notes/` itself without a path.
+pub async fn upload_attachments(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    path: Option<Path<String>>,
+    Query(query): Query<UploadQuery>,
+    mut multipart: Multipart,
+) -> Response {
+    let note_service = match get_note_service(&state, &headers) {
+        Ok(service) => service,
+        Err(e) => return error_response(e),
+    };
+    let category = path.map(|Path(path)| path).unwrap_or_default();
+
+    let mut files = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        let Some(name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        let content = match field.bytes().await {
+            Ok(content) => content,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        };
+        files.push((name, content.to_vec()));
+    }
+    if files.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No files in the request".to_string()).into_response();
+    }
+    match note_service.add_attachments(&category, files, query.overwrite).await {
+        Ok(attachments) => (StatusCode::CREATED, Json::<Vec<Attachment>>(attachments)).into_response(),
+        Err(e) => error_response(e),
     }
 }
 
-pub async fn delete_note(headers: ApiHeaders, Path(path): Path<String>) -> impl IntoResponse {
-    let note_service = match get_note_service(&headers) {
+/// Image types a browser may show inline. Anything else, SVG included as it can run scripts, is
+/// only offered as a download.
+const INLINE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+];
+
+/// The raw file at `path`, with its MIME type guessed from its name. The headers keep a file
+/// uploaded to the notes from running as a page of this app.
+fn attachment_response(path: &str, content: Vec<u8>) -> Response {
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, mime.as_ref())
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::CONTENT_SECURITY_POLICY, "sandbox");
+    if !INLINE_TYPES.contains(&mime.essence_str()) {
+        response = response.header(header::CONTENT_DISPOSITION, "attachment");
+    }
+    response.body(Body::from(content)).unwrap()
+}
+
+/// Handles `GET /api/attachments/*path`.
+pub async fn get_attachment(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(path): Path<String>,
+) -> Response {
+    let note_service = match get_note_service(&state, &headers) {
         Ok(service) => service,
-        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Err(e) => return error_response(e),
     };
+    match note_service.get_attachment(&path).await {
+        Ok(Some(content)) => attachment_response(&path, content),
+        Ok(None) => (StatusCode::NOT_FOUND, "Attachment not found".to_string()).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+pub async fn search_notes(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
+    note_service.search(&query.q).await.map(Json).map_err(error_response)
+}
+
+pub async fn list_tags(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+) -> Result<Json<Vec<TagCount>>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
+    note_service.get_tags().await.map(Json).map_err(error_response)
+}
+
+pub async fn get_tagged_notes(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(tag): Path<String>,
+) -> Result<Json<Vec<TaggedNote>>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
+    note_service
+        .get_notes_by_tag(&tag)
+        .await
+        .map(Json)
+        .map_err(error_response)
+}
+
+pub async fn check_links(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+) -> Result<Json<Vec<BrokenLinks>>, Response> {
+    let note_service = get_note_service(&state, &headers).map_err(error_response)?;
+    note_service.check_links().await.map(Json).map_err(error_response)
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    /// Delete a category and everything below it.
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+pub async fn delete_note(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(path): Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> Response {
+    let note_service = match get_note_service(&state, &headers) {
+        Ok(service) => service,
+        Err(e) => return error_response(e),
+    };
+    if query.recursive {
+        return match note_service.delete_tree(&path).await {
+            Ok(report) => Json::<DeleteReport>(report).into_response(),
+            Err(e) => error_response(e),
+        };
+    }
     match note_service.delete_note(&path).await {
-        Ok(_) => (StatusCode::OK, "Note deleted".to_string()),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Ok(_) => (StatusCode::OK, "Note deleted".to_string()).into_response(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Handles `POST /api/notes/*path/<action>` requests.
+pub async fn note_action(
+    State(state): State<Arc<AppState>>,
+    headers: ApiHeaders,
+    Path(path): Path<String>,
+    body: Bytes,
+) -> Response {
+    let note_service = match get_note_service(&state, &headers) {
+        Ok(service) => service,
+        Err(e) => return error_response(e),
+    };
+    match path.rsplit_once('/') {
+        Some((path, "restore")) => {
+            let payload: RestoreNote = match parse_body(&body) {
+                Ok(payload) => payload,
+                Err(rejection) => return rejection.into_response(),
+            };
+            match note_service.restore_note(path, &payload.rev).await {
+                Ok(_) => (StatusCode::OK, "Note restored".to_string()).into_response(),
+                Err(e) => error_response(e),
+            }
+        }
+        Some((path, "move")) => {
+            let payload: MoveNote = match parse_body(&body) {
+                Ok(payload) => payload,
+                Err(rejection) => return rejection.into_response(),
+            };
+            match note_service.move_note(path, &payload.to).await {
+                Ok(_) => (StatusCode::OK, "Note moved".to_string()).into_response(),
+                Err(e) => error_response(e),
+            }
+        }
+        Some((path, "copy")) => {
+            let payload: CopyNote = match parse_body(&body) {
+                Ok(payload) => payload,
+                Err(rejection) => return rejection.into_response(),
+            };
+            match note_service.copy_note(path, &payload.to, payload.overwrite).await {
+                Ok(_) => (StatusCode::OK, "Note copied".to_string()).into_response(),
+                Err(e) => error_response(e),
+            }
+        }
+        _ => (StatusCode::NOT_FOUND, "Unknown note action".to_string()).into_response(),
+    }
+}
+
+fn parse_body<T: DeserializeOwned>(body: &Bytes) -> Result<T, (StatusCode, String)> {
+    serde_json::from_slice(body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_bad_requests_as_such() {
+        for err in [
+            NoteServiceError::InvalidQuery("(".to_string()),
+            NoteServiceError::InvalidAttachment(".env".to_string()),
+            NoteServiceError::InvalidPath("cannot move a into itself".to_string()),
+        ] {
+            assert_eq!(error_response(err).status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(
+            error_response(NoteServiceError::Anyhow("boom".to_string())).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn only_shows_images_inline() {
+        for (path, download) in [
+            ("assets/diagram.png", false),
+            ("assets/photo.JPG", false),
+            ("assets/logo.svg", true),
+            ("assets/page.html", true),
+            ("assets/report.pdf", true),
+            ("assets/unknown", true),
+        ] {
+            let response = attachment_response(path, Vec::new());
+            let headers = response.headers();
+            assert_eq!(headers.contains_key(header::CONTENT_DISPOSITION), download, "{}", path);
+            assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+            assert_eq!(headers[header::CONTENT_SECURITY_POLICY], "sandbox");
+        }
     }
 }
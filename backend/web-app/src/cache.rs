@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+/// How many notes repositories are kept between requests. The least recently used is dropped
+/// when another one is needed.
+const CAPACITY: usize = 32;
+/// How long an entry is kept after the last request that used it.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Identifies the notes a request works on by a hash of its GitHub token and notes repository, so
+/// tokens are not kept around as keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NotesKey([u8; 32]);
+
+impl NotesKey {
+    pub fn new(github_token: Option<&str>, notes_repo: &str) -> Self {
+        let mut hasher = Sha256::new();
+        match github_token {
+            Some(token) => {
+                hasher.update([1]);
+                hasher.update((token.len() as u64).to_le_bytes());
+                hasher.update(token);
+            }
+            None => hasher.update([0]),
+        }
+        hasher.update(notes_repo);
+        Self(hasher.finalize().into())
+    }
+}
+
+/// Values kept between requests for each notes repository, bounded in number and dropped once
+/// unused for a while, so clients sending made-up tokens cannot grow it without limit.
+pub struct NotesCache<V> {
+    entries: HashMap<NotesKey, Entry<V>>,
+    capacity: usize,
+    idle_timeout: Duration,
+    /// Counts lookups, to order entries by their last use even within one clock tick.
+    lookups: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    used_at: Instant,
+    /// The value of `lookups` when the entry was last used.
+    used: u64,
+}
+
+impl<V> Default for NotesCache<V> {
+    fn default() -> Self {
+        Self::new(CAPACITY, IDLE_TIMEOUT)
+    }
+}
+
+impl<V> NotesCache<V> {
+    pub fn new(capacity: usize, idle_timeout: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+            idle_timeout,
+            lookups: 0,
+        }
+    }
+}
+
+impl<V: Clone> NotesCache<V> {
+    /// The value for `key`, or the one `create` returns, which is kept unless it is an error.
+    pub fn get_or_try_insert<E>(&mut self, key: NotesKey, create: impl FnOnce() -> Result<V, E>) -> Result<V, E> {
+        let now = Instant::now();
+        let idle_timeout = self.idle_timeout;
+        self.entries
+            .retain(|_, entry| now.duration_since(entry.used_at) < idle_timeout);
+        self.lookups += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.used_at = now;
+            entry.used = self.lookups;
+            return Ok(entry.value.clone());
+        }
+
+        let value = create()?;
+        if self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                value: value.clone(),
+                used_at: now,
+                used: self.lookups,
+            },
+        );
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    fn insert(cache: &mut NotesCache<u32>, key: NotesKey, value: u32) -> u32 {
+        cache.get_or_try_insert(key, || Ok::<_, Infallible>(value)).unwrap()
+    }
+
+    #[test]
+    fn keys_by_token_and_repository() {
+        assert_eq!(NotesKey::new(Some("t"), "o/r"), NotesKey::new(Some("t"), "o/r"));
+        assert_ne!(NotesKey::new(Some("t"), "o/r"), NotesKey::new(Some("u"), "o/r"));
+        assert_ne!(NotesKey::new(None, "o/r"), NotesKey::new(Some(""), "o/r"));
+        assert_ne!(NotesKey::new(Some("to"), "/r"), NotesKey::new(Some("t"), "o/r"));
+    }
+
+    #[test]
+    fn drops_the_least_recently_used_entry() {
+        let mut cache = NotesCache::new(2, Duration::from_secs(60));
+        let (a, b, c) = (
+            NotesKey::new(None, "a"),
+            NotesKey::new(None, "b"),
+            NotesKey::new(None, "c"),
+        );
+        insert(&mut cache, a, 1);
+        insert(&mut cache, b, 2);
+        assert_eq!(insert(&mut cache, a, 10), 1);
+        insert(&mut cache, c, 3);
+
+        assert_eq!(insert(&mut cache, a, 10), 1);
+        assert_eq!(insert(&mut cache, b, 20), 20);
+    }
+
+    #[test]
+    fn drops_idle_entries() {
+        let mut cache = NotesCache::new(2, Duration::ZERO);
+        let key = NotesKey::new(None, "a");
+        insert(&mut cache, key, 1);
+        assert_eq!(insert(&mut cache, key, 2), 2);
+    }
+
+    #[test]
+    fn keeps_nothing_when_creating_fails() {
+        let mut cache: NotesCache<u32> = NotesCache::default();
+        let key = NotesKey::new(None, "a");
+        assert!(cache.get_or_try_insert(key, || Err("offline")).is_err());
+        assert_eq!(insert(&mut cache, key, 1), 1);
+    }
+}
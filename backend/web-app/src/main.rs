@@ -1,26 +1,83 @@
-use axum::{routing::get, Router};
-use std::sync::Arc;
+use axum::{
+    extract::DefaultBodyLimit,
+    routing::{get, post},
+    Router,
+};
+use services::search::SearchIndex;
+use services::store::{NoteStore, NotesSource};
+use std::sync::{Arc, Mutex};
 use tower_http::cors::{Any, CorsLayer};
 
 mod api;
 mod assets;
+mod cache;
 
-use api::{create_note, delete_note, get_note, list_notes, update_note};
+use api::{
+    check_links, create_note, delete_note, diff_note, get_attachment, get_backlinks, get_note, get_note_history,
+    get_tagged_notes, list_notes, list_tags, note_action, search_notes, update_note, upload_attachments,
+};
 use assets::static_handler;
+use cache::NotesCache;
 
-struct AppState;
+/// Largest upload accepted by the attachment endpoints.
+const MAX_UPLOAD_BYTES: usize = 25 * 1024 * 1024;
+
+#[derive(Clone)]
+struct Notes {
+    store: Arc<dyn NoteStore>,
+    search_index: Arc<SearchIndex>,
+}
+
+#[derive(Default)]
+struct AppState {
+    /// The store and search index of each notes repository, kept between requests so that
+    /// requests to the same notes share one limit on concurrent GitHub requests and one index.
+    notes: Mutex<NotesCache<Notes>>,
+    /// The local notes directory used by requests without a `NOTES_REPO` header, from the
+    /// `LOCAL_NOTES_REPO` environment variable. Clients cannot choose a local directory.
+    local_notes_repo: Option<String>,
+}
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
 
-    let shared_state = Arc::new(AppState);
+    let local_notes_repo = std::env::var("LOCAL_NOTES_REPO").ok().filter(|repo| !repo.is_empty());
+    if let Some(repo) = &local_notes_repo {
+        if !matches!(NotesSource::parse(repo), NotesSource::Local(_)) {
+            panic!("LOCAL_NOTES_REPO must be a local directory, not {}", repo);
+        }
+    }
+    let shared_state = Arc::new(AppState {
+        local_notes_repo,
+        ..Default::default()
+    });
 
     let cors = CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any);
 
     let app = Router::new()
         .route("/api/notes", get(list_notes).post(create_note))
-        .route("/api/notes/*path", get(get_note).put(update_note).delete(delete_note))
+        .route(
+            "/api/notes/*path",
+            get(get_note).post(note_action).put(update_note).delete(delete_note),
+        )
+        .route("/api/history/*path", get(get_note_history))
+        .route("/api/backlinks/*path", get(get_backlinks))
+        .route("/api/diff/*path", get(diff_note))
+        .route(
+            "/api/attachments",
+            post(upload_attachments).layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route(
+            "/api/attachments/*path",
+            get(get_attachment)
+                .post(upload_attachments)
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route("/api/search", get(search_notes))
+        .route("/api/tags", get(list_tags))
+        .route("/api/tags/*tag", get(get_tagged_notes))
+        .route("/api/links/broken", get(check_links))
         .fallback(static_handler)
         .with_state(shared_state)
         .layer(cors);
@@ -12,7 +12,35 @@ export interface Note {
     sha: string;
     note_type: 'file' | 'dir';
     content?: string;
+    // The revision `content` was read at, to send back with `updateNote`.
+    revision?: string;
     children?: Note[];
+    // For a category, the paths below it that could not be loaded.
+    warnings?: ListWarning[];
+}
+
+// The note as it is now, returned when it was changed elsewhere since it was read.
+export interface NoteConflict {
+    current_revision: string;
+    current_content: string;
+    // Both edits merged, with conflict markers around the lines both changed.
+    merged_content?: string;
+}
+
+// A note as it was saved. `content` differs from what was sent when edits made elsewhere were
+// merged into it; `revision` is the one to send with the next save.
+export interface SavedNote {
+    revision: string;
+    content: string;
+}
+
+export class NoteConflictError extends Error {
+    conflict: NoteConflict;
+
+    constructor(conflict: NoteConflict) {
+        super('The note was changed elsewhere since it was opened.');
+        this.conflict = conflict;
+    }
 }
 
 export async function checkInitializedApi() {
@@ -55,31 +83,43 @@ export async function setCredentials() {
     }
 }
 
-export async function listNotes(): Promise<Note[]> {
+export interface ListWarning {
+    path: string;
+    error: string;
+}
+
+export interface NoteListing {
+    notes: Note[];
+    warnings: ListWarning[];
+}
+
+export async function listNotes(): Promise<NoteListing> {
 	if (settingsAreEmpty()) {
 		log('Settings are empty, returning empty list of notes.');
-		return [];
+		return { notes: [], warnings: [] };
 	}
     if (isDesktop && !get(isInitialized)) {
         log('Desktop app not initialized, returning empty list of notes.');
-        return [];
+        return { notes: [], warnings: [] };
     }
+    let listing: NoteListing;
     if (isDesktop) {
         const { invoke } = await import('@tauri-apps/api/core');
         log('Invoking list_notes');
-        const notes = await invoke('list_notes');
-        log('Received notes:', notes);
-        return notes as Note[];
+        listing = await invoke('list_notes');
     } else {
         log('Fetching notes from backend server.');
         const res = await fetch(`${get(backendUrl)}/api/notes`, {
             headers: getHeaders()
         });
         if (!res.ok) throw new Error(await res.text());
-        const notes = await res.json();
-        log('Received notes:', notes);
-        return notes;
+        listing = await res.json();
     }
+    log('Received notes:', listing.notes);
+    for (const warning of listing.warnings) {
+        log(`Failed to load ${warning.path}: ${warning.error}`);
+    }
+    return listing;
 }
 
 export async function getNote(path: string): Promise<Note> {
@@ -135,7 +175,9 @@ export async function createNote(path: string, content: string): Promise<void> {
     }
 }
 
-export async function updateNote(path: string, content: string): Promise<void> {
+// Saves `content`. With the `revision` the note was read at, the save fails with a
+// `NoteConflictError` if the note has changed since and the edits cannot be merged.
+export async function updateNote(path: string, content: string, revision?: string): Promise<SavedNote> {
 	if (settingsAreEmpty()) {
 		throw new Error('Settings are not configured: GitHub token or notes repository is empty.');
 	}
@@ -145,17 +187,27 @@ export async function updateNote(path: string, content: string): Promise<void> {
     if (isDesktop) {
         log(`Invoking update_note for path: ${path}`);
         const { invoke } = await import('@tauri-apps/api/core');
-        await invoke('update_note', { path, content });
-        log('Note updated.');
+        try {
+            const saved = (await invoke('update_note', { path, payload: { content, revision } })) as SavedNote;
+            log('Note updated.');
+            return saved;
+        } catch (error: any) {
+            if (error && typeof error === 'object' && 'Conflict' in error) {
+                throw new NoteConflictError(error.Conflict);
+            }
+            throw error;
+        }
     } else {
         log(`Updating note for path: ${path}`);
         const res = await fetch(`${get(backendUrl)}/api/notes/${path}`, {
             method: 'PUT',
             headers: getHeaders(),
-            body: JSON.stringify({ content }),
+            body: JSON.stringify({ content, revision }),
         });
+        if (res.status === 409) throw new NoteConflictError(await res.json());
         if (!res.ok) throw new Error(await res.text());
         log('Note updated.');
+        return await res.json();
     }
 }
 
@@ -182,6 +234,37 @@ export async function deleteNote(path: string): Promise<void> {
     }
 }
 
+// The outcome of deleting a category. Files in `failed` are left in place.
+export interface DeleteReport {
+    deleted: string[];
+    failed: { path: string; error: string }[];
+}
+
+export async function deleteTree(path: string): Promise<DeleteReport> {
+	if (settingsAreEmpty()) {
+		throw new Error('Settings are not configured: GitHub token or notes repository is empty.');
+	}
+    if (isDesktop && !get(isInitialized)) {
+		throw new Error('Application is not initialized.');
+	}
+    if (isDesktop) {
+        log(`Invoking delete_tree for path: ${path}`);
+        const { invoke } = await import('@tauri-apps/api/core');
+        const report = (await invoke('delete_tree', { path })) as DeleteReport;
+        log('Category deleted.');
+        return report;
+    } else {
+        log(`Deleting category for path: ${path}`);
+        const res = await fetch(`${get(backendUrl)}/api/notes/${path}?recursive=true`, {
+            method: 'DELETE',
+            headers: getHeaders(),
+        });
+        if (!res.ok) throw new Error(await res.text());
+        log('Category deleted.');
+        return await res.json();
+    }
+}
+
 import { addLog } from './logStore';
 
 export async function log(message: string): Promise<void> {